@@ -0,0 +1,85 @@
+// Count the nodes of the game tree to validate the move generator.
+//
+// Usage: 0-perft depth [position] [--divide] [--merged]
+//
+//   position: SFEN-like notation (see sfen.rs) or hex representation of
+//             bit-board (default: the initial board)
+//   --divide: count the nodes for each first move
+//   --merged: merge transpositions (count distinct boards)
+//
+// Input: none
+//
+// Output:
+//   depth count
+//   ...
+//
+//   (or, with --divide)
+//   move count
+//   ...
+//
+//   move: ASCII notation of the first move (see kifu.rs)
+//
+//   (a board that is already won or lost has no move to count, which is
+//   logged instead)
+
+#[macro_use]
+extern crate precomp;
+
+use std::env;
+use std::process;
+use precomp::Out;
+use precomp::board::{Board, Color, Status};
+use precomp::kifu::{self, Style};
+use precomp::perft;
+use precomp::sfen;
+
+fn usage() -> ! {
+    log!("usage: 0-perft depth [position] [--divide] [--merged]");
+    process::exit(1)
+}
+
+fn main() {
+    let mut depth = None;
+    let mut pos = (Board::init(), Color::Black);
+    let mut divide = false;
+    let mut merged = false;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--divide" => divide = true,
+            "--merged" => merged = true,
+            _ if depth.is_none() => depth = Some(arg.parse().unwrap_or_else(|_| usage())),
+            _ => {
+                pos = match sfen::parse(&arg) {
+                    Ok(pos) => pos,
+                    Err(e) => match arg.parse() {
+                        Ok(b) => (b, Color::Black),
+                        Err(_) => { log!("{}", e); usage() }
+                    }
+                }
+            }
+        }
+    }
+    let depth = depth.unwrap_or_else(|| usage());
+    let (b, side) = pos;
+
+    let mut out = Out::new();
+    if divide {
+        match b.status() {
+            Status::Win => log!("the opponent's lion can be captured"),
+            Status::Lose => log!("the opponent has succeeded \"try\""),
+            Status::Unknown => (),
+        }
+        let mut total = 0;
+        for (m, n) in perft::divide(b, depth, merged) {
+            out!(out, "{} {}\n", kifu::write_move(b, side, m, Style::Ascii), n);
+            total += n;
+        }
+        log!("total: {}", total);
+    }
+    else {
+        for d in 0..depth + 1 {
+            let n = if merged { perft::perft_merged(b, d) } else { perft::perft(b, d) };
+            out!(out, "{} {}\n", d, n);
+        }
+    }
+}
@@ -2,7 +2,7 @@
 //
 // Input: none
 //
-// Output:
+// Output (`--format text`, the default):
 //   board depth
 //   ...
 //
@@ -11,22 +11,26 @@
 //      1: winning board (the player can capture the opponent's lion)
 //      0: losing board (the opponent succeeded "try")
 //     -1: unknown board
+//
+// `--format binary` writes the same records in the binary format (see
+// Format in lib.rs).
 
 #[macro_use]
 extern crate precomp;
 
 use std::cmp;
-use precomp::Out;
-use precomp::board::{Board, Result};
+use precomp::{Format, Header, Order, Out};
+use precomp::board::{Board, Status};
 use precomp::board_collection::BoardSet;
 
 fn main() {
     log!("Step 1: enumerate all reachable boards");
 
-    let mut item_counts = vec![0, 0, 0];
+    let mut item_counts = [0, 0, 0];
     let mut max_degree = 0;
 
-    let mut out = Out::new();
+    // the number of boards is unknown until the end
+    let mut out = Out::records(Format::from_args(), Header { stage: 1, order: Order::Unsorted, idx: false, count: None });
     let mut boards = vec![Board::init().normalize()];
     let mut visited = BoardSet::new();
 
@@ -35,17 +39,17 @@ fn main() {
         if visited.contains(b) { continue };
         visited.insert(b);
 
-        let r = match b.next() {
-            Result::Win  => { 1 },
-            Result::Lose => { 0 },
-            Result::Unknown(bs) => {
-                max_degree = cmp::max(max_degree, bs.len());
-                for b in bs { boards.push(b) }
+        let mut degree = 0;
+        let r = match b.each_next(|b| { degree += 1; boards.push(b); true }) {
+            Status::Win  => { 1 },
+            Status::Lose => { 0 },
+            Status::Unknown => {
+                max_degree = cmp::max(max_degree, degree);
                 -1
             }
         };
 
-        out!(out, "{:015x} {}\n", b.0, r);
+        out.record(b, r);
 
         item_counts[(1 - r) as usize] += 1;
         if visited.len() % 10000000 == 0 {
@@ -1,8 +1,8 @@
 // Determine the depth of each board by retrospective analysis.
 //
-// Input: 1-enum's output
+// Input: 1-enum's output (in either format)
 //
-// Output:
+// Output (`--format text`, the default):
 //   board depth
 //   ...
 //
@@ -11,13 +11,46 @@
 //     odd: black will win
 //     even: white will win
 //     -1: draw
+//
+// `--format binary` writes the same records in the binary format (see
+// Format in lib.rs).
+//
+// `--threads N` analyzes each depth by N threads (default: the number of
+// CPUs).  The output is the same regardless of N, except the order of boards
+// of the same depth.
+//
+// `--counter` uses counter-based retrograde analysis instead (see
+// enumerate_next_boards_by_counters).  The output is the same, except the
+// order of boards of the same depth.  It is experimental: on the first 30M
+// boards of 1.txt (one thread), it takes 93s against 63s of the default
+// method, and it keeps a counter per unfixed board.  It is kept as an
+// independent check of the default method (tests/analyze.rs compares their
+// outputs) and of Board::prev, which it needs to be the exact inverse of
+// Board::next.  The two have not been compared on the full game (`make
+// bench`), which needs more memory than the 30M boards.
+//
+// `--checkpoint dir` saves the state of the analysis into dir after a depth
+// is completed (at most once per `--checkpoint-interval` seconds, default:
+// 600), and `--checkpoint dir --resume` restarts from the last checkpoint
+// without reading the input.  The output of a resumed run is the same as the
+// output of an uninterrupted run.  `--max-depth N` saves a checkpoint and
+// stops after depth N (to split the analysis into several runs).
 
 #[macro_use]
 extern crate precomp;
 
-use precomp::{In, Out};
-use precomp::board::{Board, Result};
-use precomp::board_collection::BoardSet;
+use std::cmp;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+use precomp::{Format, Header, In, Order, Out, Records, HEADER_SIZE, RECORD_SIZE};
+use precomp::board::Board;
+use precomp::board_collection::{BoardSet, BoardMap};
 
 #[derive(Default)]
 struct State {
@@ -25,10 +58,20 @@ struct State {
     next_boards: Vec<Board>, // board list of depth-N
     fixed: BoardSet,        // boards whose depth is already fixed
     unfixed: BoardSet,      // boards whose depth is not fixed yet
+    counts: BoardMap<u8>,     // the number of next boards not fixed yet (--counter)
+}
+
+// the progress of the analysis
+#[derive(Default)]
+struct Progress {
+    depth: usize,             // the depth to analyze next
+    count: u64,               // the number of all boards
+    init_depth: usize,
+    board_counts: [usize; 2], // black-winning and white-winning
 }
 
 // load all possible boards
-fn load() -> State {
+fn load(check: bool) -> State {
     fn log(msg: &str, fixed: usize, unfixed: usize) {
         log!("{} (unfixed: {}, fixed: {}, total: {})",
             msg, unfixed, fixed, unfixed + fixed);
@@ -36,7 +79,7 @@ fn load() -> State {
 
     let mut s = State::default();
 
-    In::each(|b, depth, _| {
+    In::each(check, |b, depth, _| {
         if depth == 0 {
             // a depth-0 board is fixed
             s.fixed.insert(b);
@@ -58,8 +101,22 @@ fn load() -> State {
     s
 }
 
+// split 0..n into chunks, run f for each chunk in parallel, and concatenate
+// the results in order
+fn parallel<T, F>(threads: usize, n: usize, f: F) -> Vec<T>
+    where T: Send, F: Fn(Range<usize>) -> Vec<T> + Sync {
+    let chunk = cmp::max(1, (n + threads - 1) / threads);
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..n).step_by(chunk).map(|i| {
+            scope.spawn(move || f(i..cmp::min(i + chunk, n)))
+        }).collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    })
+}
+
 // identify all depth-N boards
-fn enumerate_next_boards(s: &mut State, depth: i32) {
+fn enumerate_next_boards(s: &mut State, depth: i32, threads: usize) {
     // We can determine a board B is depth-N, only if:
     //   (1) N is odd (white's turn) and any B's next board is depth-{N-1}, or
     //   (2) N is even (black's turn) and all B's next boards are depth-{N-1}
@@ -68,105 +125,398 @@ fn enumerate_next_boards(s: &mut State, depth: i32) {
 
     // Check if a depth-N board candidate satisfies the conditions above
     #[inline]
-    fn check(fixed: &BoardSet, b: Board, depth: i32, next_boards: &mut Vec<Board>) {
+    fn check(fixed: &BoardSet, b: Board, depth: i32) -> bool {
         if depth % 2 != 0 {
-            if let Result::Unknown(bs) = b.next() {
-                for b in bs {
-                    if !fixed.contains(b) { return }
-                }
-            }
+            let mut all = true;
+            b.each_next(|b| {
+                all = fixed.contains(b);
+                all
+            });
+            return all
         }
-        next_boards.push(b)
+        true
     }
 
     let prev_boards = &s.prev_boards;
     let fixed = &s.fixed;
     let unfixed = &s.unfixed;
-    let mut next_boards = &mut s.next_boards;
 
     // There are two approaches to enumerate depth-N board candidates:
     //   1) calculate back the depth-N candidates from all depth-{N-1} boards
     //   2) filter the depth-N candidates that can proceed to any depth-N board
-    if prev_boards.len() * 4 < unfixed.len() {
+    //
+    // Each approach splits the work among threads: 1) by chunks of depth-{N-1}
+    // boards, and 2) by ranges of buckets of the unfixed set.
+    let next_boards = if prev_boards.len() * 4 < unfixed.len() {
         // approach 1
-        let mut visited = BoardSet::new();
-        for b in prev_boards { // depth-{N-1} boards
-            for b in b.prev() { // calculate candidates
-                if unfixed.contains(b) { // skip unreachable board
-                    if !visited.contains(b) { // avoid duplication
-                        visited.insert(b);
-                        // candidate found
-                        check(fixed, b, depth, &mut next_boards);
+        let candidates = parallel(threads, prev_boards.len(), |r| {
+            let mut visited = BoardSet::new();
+            let mut candidates = vec![];
+            for b in &prev_boards[r] { // depth-{N-1} boards
+                for b in b.prev() { // calculate candidates
+                    if unfixed.contains(b) { // skip unreachable board
+                        if !visited.contains(b) { // avoid duplication
+                            visited.insert(b);
+                            candidates.push(b);
+                        }
                     }
                 }
             }
-        }
+            candidates
+        });
+
+        // remove the duplication among threads
+        let mut visited = BoardSet::new();
+        let candidates: Vec<Board> = candidates.into_iter().filter(|&b| {
+            if visited.contains(b) { return false }
+            visited.insert(b);
+            true
+        }).collect();
+
+        parallel(threads, candidates.len(), |r| {
+            candidates[r].iter().cloned().filter(|&b| check(fixed, b, depth)).collect()
+        })
     }
     else {
         // approach 2
-        let mut prev_set = BoardSet::new();
-        for &pb in prev_boards {
-            prev_set.insert(pb);
-        }
-        unfixed.each(|b| {
-            if let Result::Unknown(bs) = b.next() {
-                for nb in bs {
-                    if prev_set.contains(nb) {
-                        // candidate found
-                        check(fixed, b, depth, &mut next_boards);
-                        return;
-                    }
+        let prev_set: BoardSet = prev_boards.iter().cloned().collect();
+        parallel(threads, unfixed.buckets(), |r| {
+            unfixed.iter_range(r).filter(|&b| {
+                let mut found = false;
+                b.each_next(|nb| {
+                    found = prev_set.contains(nb);
+                    !found
+                });
+                found // candidate found
+            }).filter(|&b| check(fixed, b, depth)).collect()
+        })
+    };
+
+    // the depth-1 boards given by the input (see load) may be found again
+    if s.next_boards.is_empty() { s.next_boards = next_boards; return }
+    let given: BoardSet = s.next_boards.iter().cloned().collect();
+    s.next_boards.extend(next_boards.into_iter().filter(|&b| !given.contains(b)));
+}
+
+// the number of boards processed at a time by the counter-based analysis
+const BATCH : usize = 1 << 20;
+
+// distinct boards (Board::prev and Board::next may have duplicates)
+fn uniq(mut bs: Vec<Board>) -> Vec<Board> {
+    bs.sort_by_key(|b| b.0);
+    bs.dedup();
+    bs
+}
+
+// count the next boards of all unfixed boards (for the counter-based analysis)
+fn count_next_boards(s: &mut State, threads: usize) {
+    let unfixed = &s.unfixed;
+    let counts = &mut s.counts;
+    for i in (0..unfixed.buckets()).step_by(BATCH) {
+        let r = i..cmp::min(i + BATCH, unfixed.buckets());
+        let cs = parallel(threads, r.len(), |r2| {
+            unfixed.iter_range(r.start + r2.start..r.start + r2.end).map(|b| {
+                let mut bs = vec![];
+                b.each_next(|nb| { bs.push(nb); true });
+                (b, uniq(bs).len() as u8)
+            }).collect()
+        });
+        counts.extend(cs);
+    }
+    log!("counted! (boards: {})", counts.len());
+}
+
+// identify all depth-N boards by counter-based retrograde analysis
+fn enumerate_next_boards_by_counters(s: &mut State, depth: i32, threads: usize) {
+    // Each unfixed board B keeps the number of its next boards whose depth is
+    // not fixed yet.  For each depth-{N-1} board:
+    //   (1) N is odd: all its previous boards are depth-N
+    //   (2) N is even: the counts of its previous boards are decremented, and
+    //       a board is depth-N when its count reaches zero
+    //
+    // Unlike enumerate_next_boards, the next boards of a candidate are never
+    // generated again.  This relies on Board::prev returning exactly the
+    // boards whose next boards include the given board.
+
+    let unfixed = &s.unfixed;
+    let counts = &mut s.counts;
+    let next_boards = &mut s.next_boards;
+    // the depth-1 boards given by the input (see load) are already found
+    let mut visited: BoardSet = next_boards.iter().cloned().collect();
+
+    for prev_boards in s.prev_boards.chunks(BATCH) {
+        // calculate back the candidates in parallel
+        let candidates = parallel(threads, prev_boards.len(), |r| {
+            let mut candidates = vec![];
+            for b in &prev_boards[r] {
+                for b in uniq(b.prev()) {
+                    if unfixed.contains(b) { candidates.push(b) }
                 }
             }
+            candidates
         });
+
+        for b in candidates {
+            if depth % 2 == 0 {
+                if visited.contains(b) { continue }
+                visited.insert(b);
+                next_boards.push(b);
+            }
+            else {
+                let c = &mut counts[b];
+                *c -= 1;
+                if *c == 0 { next_boards.push(b) }
+            }
+        }
     }
 }
 
-fn main() {
-    log!("Step 2: perform retrospective analysis");
+// the value of an option (`--name value`)
+fn option(name: &str) -> Option<String> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == name { return Some(args.next().unwrap_or_default()) }
+    }
+    None
+}
 
-    let mut out = Out::new();
-    let mut s = load();
-    let init_board = Board::init().normalize();
+fn parse_option<T: std::str::FromStr>(name: &str) -> Option<T> {
+    option(name).map(|v| v.parse().unwrap_or_else(|_| {
+        log!("invalid value of {}: {:?}", name, v);
+        process::exit(1)
+    }))
+}
 
-    let mut board_counts = vec![0, 0];
-    let mut init_depth = 0;
+// the number of threads (`--threads N`, or the number of CPUs)
+fn threads() -> usize {
+    match parse_option("--threads") {
+        Some(0) => { log!("invalid number of threads"); process::exit(1) }
+        Some(n) => n,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    }
+}
 
-    let mut depth = 0;
+// checkpoint (`--checkpoint dir`)
+//
+//   dir/records: all output records so far (in the binary format)
+//   dir/unfixed-N, dir/counts-N: the images of the sets after depth N-1
+//   dir/state: "N records count init_depth black white counter"
+//
+// dir/records is appended as the analysis proceeds, and the state is written
+// last, so a crash while saving leaves the previous checkpoint valid.
+struct Checkpoint {
+    dir: PathBuf,
+    records: Option<BufWriter<File>>,
+    n: u64,             // the number of records written
+    interval: Duration,
+    last: Instant,      // the time of the last checkpoint
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Checkpoint {
+    fn new(dir: PathBuf, interval: Duration) -> Self {
+        Checkpoint { dir, records: None, n: 0, interval, last: Instant::now() }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    // start a new analysis (the old checkpoint is discarded)
+    fn start(&mut self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        if self.path("state").exists() { fs::remove_file(self.path("state"))? }
+        let mut w = BufWriter::new(File::create(self.path("records"))?);
+        Header { stage: 2, order: Order::Depth, idx: false, count: None }.write(&mut w)?;
+        self.records = Some(w);
+        Ok(())
+    }
+
+    fn record(&mut self, b: Board, depth: i32) {
+        let w = self.records.as_mut().unwrap();
+        Format::Binary.write(w, b, depth).expect("failed writing a checkpoint");
+        self.n += 1;
+    }
 
-    for b in &s.prev_boards { out!(out, "{:015x} 0\n", b.0); }
+    fn due(&self) -> bool {
+        self.last.elapsed() >= self.interval
+    }
+
+    fn save(&mut self, s: &State, p: &Progress, counter: bool) -> io::Result<()> {
+        // the records must reach the disk before the state that counts them
+        let w = self.records.as_mut().unwrap();
+        w.flush()?;
+        w.get_ref().sync_data()?;
+
+        let write = |name: String, f: &dyn Fn(&mut BufWriter<File>) -> io::Result<()>| -> io::Result<()> {
+            let mut w = BufWriter::new(File::create(self.path(&name))?);
+            f(&mut w)?;
+            w.into_inner()?.sync_all()
+        };
+        write(format!("unfixed-{}", p.depth), &|w| s.unfixed.write_to(w))?;
+        if counter { write(format!("counts-{}", p.depth), &|w| s.counts.write_to(w))? }
+        write("state.tmp".to_string(), &|w| {
+            writeln!(w, "{} {} {} {} {} {} {}", p.depth, self.n, p.count, p.init_depth,
+                p.board_counts[0], p.board_counts[1], counter as u8)
+        })?;
+        fs::rename(self.path("state.tmp"), self.path("state"))?;
+
+        // remove the older images
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            let old = ["unfixed-", "counts-"].iter().any(|prefix| {
+                name.starts_with(prefix) && name[prefix.len()..].parse() != Ok(p.depth)
+            });
+            if old { fs::remove_file(self.path(&name))? }
+        }
+
+        self.last = Instant::now();
+        log!("checkpoint saved (depth: {}, records: {})", p.depth, self.n);
+        Ok(())
+    }
+
+    // read the progress of the last checkpoint
+    fn load_progress(&mut self, counter: bool) -> io::Result<Progress> {
+        let state = fs::read_to_string(self.path("state"))?;
+        let ns: Vec<u64> = state.split_whitespace().map(|n| n.parse()).collect::<Result<_, _>>()
+            .map_err(|e| invalid(format!("broken state: {}", e)))?;
+        if ns.len() != 7 { return Err(invalid("broken state".to_string())) }
+        if (ns[6] != 0) != counter { return Err(invalid("--counter does not match the checkpoint".to_string())) }
+        self.n = ns[1];
+        Ok(Progress {
+            depth: ns[0] as usize,
+            count: ns[2],
+            init_depth: ns[3] as usize,
+            board_counts: [ns[4] as usize, ns[5] as usize],
+        })
+    }
+
+    // restore the state of the last checkpoint, calling f for each record
+    // written so far
+    fn load_state<F: FnMut(Board, i32)>(&mut self, p: &Progress, counter: bool, mut f: F) -> io::Result<State> {
+        let mut s = State::default();
+        s.fixed.reserve(self.n as usize);
+        let records = Records::new(BufReader::new(File::open(self.path("records"))?), false)?;
+        let mut n = 0;
+        for r in records.take(self.n as usize) {
+            let (b, depth, _) = r.map_err(invalid)?;
+            f(b, depth);
+            s.fixed.insert(b);
+            if depth as usize == p.depth { s.prev_boards.push(b) }
+            n += 1;
+        }
+        if n != self.n { return Err(invalid(format!("too few records ({} < {})", n, self.n))) }
+
+        let open = |name: String| File::open(self.path(&name)).map(BufReader::new);
+        s.unfixed = BoardSet::read_from(&mut open(format!("unfixed-{}", p.depth))?)?;
+        if counter { s.counts = BoardMap::read_from(&mut open(format!("counts-{}", p.depth))?)? }
+
+        // drop the records after the checkpoint, and continue writing
+        let mut file = OpenOptions::new().write(true).open(self.path("records"))?;
+        file.set_len((HEADER_SIZE + RECORD_SIZE * self.n as usize) as u64)?;
+        file.seek(SeekFrom::End(0))?;
+        self.records = Some(BufWriter::new(file));
+        Ok(s)
+    }
+}
+
+fn main() {
+    log!("Step 2: perform retrospective analysis");
+
+    let format = Format::from_args();
+    let threads = threads();
+    let counter = env::args().skip(1).any(|a| a == "--counter");
+    let resume = env::args().skip(1).any(|a| a == "--resume");
+    let check = env::args().skip(1).any(|a| a == "--check");
+    let max_depth: Option<usize> = parse_option("--max-depth");
+    let interval = Duration::from_secs(parse_option("--checkpoint-interval").unwrap_or(600));
+    let mut ckpt = option("--checkpoint").map(|dir| Checkpoint::new(PathBuf::from(dir), interval));
+    if max_depth.is_some() && ckpt.is_none() {
+        log!("--max-depth needs --checkpoint");
+        process::exit(1)
+    }
+    let fail = |e: io::Error| -> ! {
+        log!("checkpoint: {}", e);
+        process::exit(1)
+    };
+
+    let mut out;
+    let mut s;
+    let mut p;
+    match (resume, ckpt.as_mut()) {
+        (true, None) => {
+            log!("--resume needs --checkpoint");
+            process::exit(1)
+        }
+        (true, Some(ckpt)) => {
+            p = ckpt.load_progress(counter).unwrap_or_else(|e| fail(e));
+            log!("resuming from depth {}...", p.depth);
+            out = Out::records(format, Header { stage: 2, order: Order::Depth, idx: false, count: Some(p.count) });
+            s = ckpt.load_state(&p, counter, |b, depth| out.record(b, depth)).unwrap_or_else(|e| fail(e));
+        }
+        (false, _) => {
+            s = load(check);
+            if counter { count_next_boards(&mut s, threads) }
+            p = Progress::default();
+            p.count = (s.fixed.len() + s.unfixed.len()) as u64;
+            out = Out::records(format, Header { stage: 2, order: Order::Depth, idx: false, count: Some(p.count) });
+            if let Some(ckpt) = ckpt.as_mut() {
+                ckpt.start().unwrap_or_else(|e| fail(e));
+                for &b in &s.prev_boards { ckpt.record(b, 0) }
+            }
+            for &b in &s.prev_boards { out.record(b, 0); }
+        }
+    }
+    let init_board = Board::init().normalize();
 
     // retrospective analysis
-    while s.prev_boards.len() > 0 || depth == 0 {
-        board_counts[depth % 2] += s.prev_boards.len();
+    while !s.prev_boards.is_empty() || p.depth == 0 {
+        let depth = p.depth;
+        p.board_counts[depth % 2] += s.prev_boards.len();
 
         log!("analyzing... (depth-{} boards: {}, unfixed boards: {})",
             depth, s.prev_boards.len(), s.unfixed.len());
 
         // identify all depth-N boards from depth-{N-1} boards
-        enumerate_next_boards(&mut s, depth as i32);
+        if counter {
+            enumerate_next_boards_by_counters(&mut s, depth as i32, threads);
+        }
+        else {
+            enumerate_next_boards(&mut s, depth as i32, threads);
+        }
 
+        s.fixed.insert_all(&s.next_boards);
         for &b in &s.next_boards {
-            s.fixed.insert(b);
             s.unfixed.delete(b);
-            out!(out, "{:015x} {}\n", b.0, depth + 1);
-            if b == init_board { init_depth = depth; }
+            out.record(b, depth as i32 + 1);
+            if let Some(ckpt) = ckpt.as_mut() { ckpt.record(b, depth as i32 + 1) }
+            if b == init_board { p.init_depth = depth; }
         }
 
-        s.prev_boards = s.next_boards;
-        s.next_boards = vec![];
-        let len = s.unfixed.len();
-        s.unfixed.resize(len);
-        depth += 1;
+        s.prev_boards = std::mem::take(&mut s.next_boards);
+        s.unfixed.shrink_to_fit();
+        p.depth += 1;
+
+        let stop = max_depth == Some(depth);
+        if let Some(ckpt) = ckpt.as_mut() {
+            if stop || ckpt.due() { ckpt.save(&s, &p, counter).unwrap_or_else(|e| fail(e)) }
+        }
+        if stop {
+            log!("Step 2: stopped after depth {} (run with --resume to continue)", depth);
+            return
+        }
     }
 
-    s.unfixed.each(|b| { out!(out, "{:015x} -1\n", b.0); }); // draw
+    for b in &s.unfixed { out.record(b, -1); } // draw
 
     log!("Step 2: result");
-    log!("  black-winning boards: {:9}", board_counts[0]);
-    log!("  white-winning boards: {:9}", board_counts[1]);
+    log!("  black-winning boards: {:9}", p.board_counts[0]);
+    log!("  white-winning boards: {:9}", p.board_counts[1]);
     log!("  draw                : {:9}", s.unfixed.len());
-    log!("  max depth : {:3}", depth - 1);
-    log!("  init depth: {:3}", init_depth);
+    log!("  max depth : {:3}", p.depth - 1);
+    log!("  init depth: {:3}", p.init_depth);
     log!("Step 2: done!");
 }
@@ -1,7 +1,11 @@
 // Extract the subset of boards that are strictly reachable from the initial
 // board.
 //
-// Input: 2-analyze's output
+// Input: 2-analyze's output (in either format)
+//
+// With `--image path`, the boards are mapped from an image of the tablebase
+// (see `dobutsu image`) instead of being read from stdin.  `--format binary`
+// writes the output in the binary format (see DagNode in lib.rs).
 //
 // Output:
 //   board depth name
@@ -19,9 +23,11 @@
 extern crate precomp;
 
 use std::cmp;
-use precomp::{In, Out};
+use std::env;
+use std::process;
+use precomp::{DagNode, Format, In, Out};
 use precomp::board::{Board, Result, ELEPHANT, GIRAFFE, CHICK};
-use precomp::board_collection::{BoardSet, BoardMap};
+use precomp::board_collection::{BoardSet, BoardMap, MapImage};
 
 struct Move {
     idx: u8,
@@ -35,13 +41,13 @@ struct Node {
 }
 
 // load all possible boards
-fn load() -> BoardMap {
+fn load(check: bool) -> BoardMap<i8> {
     fn log(msg: &str, boards: usize) {
         log!("{} (boards: {})", msg, boards);
     }
     let mut oracle = BoardMap::new();
-    In::each(|b, depth, _| {
-        oracle[b] = depth;
+    In::each(check, |b, depth, _| {
+        oracle[b] = depth as i8;
         if oracle.len() % 10000000 == 0 {
             log("loading...", oracle.len());
         }
@@ -57,8 +63,18 @@ fn check_hopeless(b: Board, nb: Board) -> bool {
     b.0 == 0x000a0030c41b902u64 && nb.0 == 0x400a01390c0b002u64
 }
 
-// extract strictly reachable boards
-fn extract(oracle: BoardMap) -> Vec<Node> {
+// map all possible boards from an image
+fn map(path: &str) -> MapImage<i8> {
+    let oracle = MapImage::open(path).unwrap_or_else(|e| {
+        log!("{}: {}", path, e);
+        process::exit(1)
+    });
+    log!("mapped! (boards: {})", oracle.len());
+    oracle
+}
+
+// extract strictly reachable boards (oracle: the depth of a board)
+fn extract<F>(oracle: F) -> Vec<Node> where F: Fn(Board) -> Option<i8> {
     // The definition of "strictly reachable"
     //  * The initial board is strictly reachable.
     //  * If a black board is strictly reachable, all white boards from the black
@@ -83,7 +99,10 @@ fn extract(oracle: BoardMap) -> Vec<Node> {
         if visited.contains(b) { continue }
         visited.insert(b);
 
-        let depth = oracle[b];
+        let depth = match oracle(b) {
+            Some(depth) => depth as i32,
+            None => { log!("unknown board: {:015x}", b.0); process::exit(1) }
+        };
         if visited.len() % 1000000 == 0 {
             log!("extracting... (visited: {}, remaining: {})",
                 visited.len(), boards.len());
@@ -113,7 +132,7 @@ fn extract(oracle: BoardMap) -> Vec<Node> {
             for (i, &nb) in bs.iter().enumerate() {
                 // record all black boards (even depth),
                 // and white boards (odd depth, only best move)
-                if depth % 2 == 0 || oracle[nb] == depth - 1 {
+                if depth % 2 == 0 || oracle(nb) == Some((depth - 1) as i8) {
                     // ad-hoc heuristic: manually prune hopeless branches
                     if check_hopeless(b, nb) { continue }
 
@@ -137,55 +156,82 @@ fn extract(oracle: BoardMap) -> Vec<Node> {
     nodes
 }
 
-fn output(nodes: Vec<Node>) {
-    let mut out = Out::new();
+// the index of a node, and its name (white boards only)
+#[derive(Clone, Copy, Default)]
+struct Entry {
+    node: usize,
+    name: Option<u32>,
+}
 
-    let mut map = BoardMap::new();
-    let mut names = BoardMap::new();
+fn output(nodes: Vec<Node>, format: Format) {
+    let mut map: BoardMap<Entry> = BoardMap::new();
     let mut name = 0;
     for (i, node) in nodes.iter().enumerate() {
-        map[node.board] = i as i32;
+        let mut e = Entry { node: i, name: None };
         if node.depth % 2 == 1 {
-            names[node.board] = name;
+            e.name = Some(name);
             name += 1;
         }
+        map[node.board] = e;
     }
-    for ref m in &nodes[map[Board::init().normalize()] as usize].next_boards {
-        out!(out, " {}", names[m.board]);
+    let name = |b: Board| map.get(b).and_then(|e| e.name);
+    let init = match map.get(Board::init().normalize()) {
+        Some(e) => &nodes[e.node],
+        None => { log!("the initial board is not extracted"); process::exit(1) }
+    };
+    let mut start = vec![];
+    for m in &init.next_boards {
+        match name(m.board) {
+            Some(n) => start.push(n),
+            None => {
+                log!("a next board of the initial board has no name: {:015x}", m.board.0);
+                process::exit(1)
+            }
+        }
     }
-    out!(out, "\n");
-    for node in &nodes {
-        // black boards are omitted
-        if node.depth % 2 == 0 { continue }
 
-        // depth-3 (or less) boards are omitted
-        if node.depth <= 3 { continue }
+    // black boards and depth-3 (or less) boards are omitted
+    let omitted = |node: &Node| node.depth % 2 == 0 || node.depth <= 3;
+    let count = nodes.iter().filter(|node| !omitted(node)).count();
+    let mut out = Out::dag(format, count as u64, &start);
+    for node in &nodes {
+        if omitted(node) { continue }
 
         // print this white board
-        out!(out, "{:015x} {} {}\n", node.board.0, node.depth, names[node.board]);
+        let mut dag_node = DagNode { board: node.board, depth: node.depth, name: name(node.board).unwrap(), moves: vec![] };
 
         // print all next white boards for each best move
-        for ref m in &node.next_boards {
-            if !map.contains(m.board) { continue };
-            let ref nnode = nodes[map[m.board] as usize];
+        for m in &node.next_boards {
+            let nnode = match map.get(m.board) {
+                Some(e) => &nodes[e.node],
+                None => continue,
+            };
             if nnode.depth % 2 != 0 { unreachable!() }
 
-            out!(out, " {}:", m.idx);
-            for ref m in &nnode.next_boards {
-                if names.contains(m.board) { out!(out, " {}", names[m.board]) }
-            }
-            out!(out, "\n");
+            let names = nnode.next_boards.iter().filter_map(|m| name(m.board)).collect();
+            dag_node.moves.push((m.idx as i32, names));
         }
-        out!(out, "\n");
+        out.node(&dag_node);
     }
 }
 
 fn main() {
     log!("Step 3: extract an subset of needed boards");
 
-    let oracle = load();
-    let nodes = extract(oracle);
-    output(nodes);
+    let format = Format::from_args();
+    let mut args = env::args().skip_while(|a| a != "--image");
+    let nodes = match (args.next(), args.next()) {
+        (Some(_), Some(path)) => {
+            let oracle = map(&path);
+            extract(|b| oracle.get(b))
+        }
+        (Some(_), None) => { log!("--image: missing path"); process::exit(1) }
+        (None, _) => {
+            let oracle = load(env::args().skip(1).any(|a| a == "--check"));
+            extract(|b| oracle.get(b).cloned())
+        }
+    };
+    output(nodes, format);
 
     log!("Step 3: done!");
 }
@@ -0,0 +1,519 @@
+// Minimize the DAG by encoding it to 0-1 integer programming.
+//
+// Usage:
+//   (1) generate .lp file:        4-opt 3.txt 4.lp
+//   (2) invert .sol file to .txt: 4-opt 3.txt 4.sol 4.lp.map
+//
+// 3.txt may be in either format; `--format binary` writes the output of (2)
+// as binary records with move indexes (see Format in lib.rs).
+//
+// This is a port of 4-opt.rb; it generates the same .lp and .lp.map files
+// (tests/opt.rs checks a small DAG against the output traced through 4-opt.rb).
+//
+// What problem we want to solve: Extract the minimal sub-DAG from the whole DAG
+// that consists of "any" and "all" nodes.
+//
+//   - If we keep "any" node, we must also keep at least one child of the node.
+//   - If we keep "all" node, we must also keep all children of the node.
+//   - We must keep four nodes proceeded by the initial board.
+//   - We want to minimize the total number of "any" nodes.
+//
+// How to solve the problem: Encode it to the following 0-1 integer programming,
+//
+//   - Assign binary variables to each node.
+//   - For the initial four nodes, n = 1.
+//   - For "any" node p and its children, c_i, c_1 + c_2 + ...+ c_N -   p >= 0.
+//   - For "all" node p and its children, c_i, c_1 + c_2 + ...+ c_N - N p >= 0.
+//   - The objection function is Sum(p) for all "any" nodes p.
+//   - If a variable of a node p is 1, we must keep the corresponding board.
+//
+// How to golf .lp file:
+//
+//   (1) trivial propagation: if there are constraints 'p = 1' and 'c - p >= 0',
+//       remove them and add a new constraint 'c = 1'.  (We call p is "pinned".)
+//   (2) unused constraints: remove a node that has no weight and children.
+//   (3) trivial merge: if there are `c1 - p >= 0' and `c2 - c1 >= 0`, remove
+//       them and add a new constraint 'c2 - p >= 0'.
+//
+// Input: 3-extract's output (and the solution of .lp file for (2))
+//
+// Output (for (2)):
+//   board depth idx
+//   ...
+//
+//   board: hex representation of bit-board
+//   depth: the depth of the board
+//   idx: move index (the index of return value of Board#next)
+
+#[macro_use]
+extern crate precomp;
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::process;
+use precomp::{Format, Header, Nodes, Order, Out};
+use precomp::board::Board;
+use precomp::board_collection::BoardMap;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind { Any, All }
+
+// The definition of a node:
+//   kind: Any or All
+//   parents: previous nodes
+//   children: next nodes
+//   weight: coefficient in objective function
+//   board: the corresponding board (only for "any" node)
+struct Node {
+    kind: Kind,
+    parents: Vec<usize>,
+    children: Vec<usize>,
+    weight: u32,
+    board: Option<Board>,
+    var: Option<String>,
+    value: bool,
+    pinned: bool,
+    merged: bool,
+}
+
+impl Node {
+    fn new(kind: Kind, weight: u32, board: Option<Board>) -> Self {
+        Node {
+            kind, parents: vec![], children: vec![], weight, board,
+            var: None, value: false, pinned: false, merged: false,
+        }
+    }
+}
+
+struct Dag {
+    nodes: Vec<Node>,
+    start_nodes: Vec<usize>,
+    // the set of nodes whose assignment we want to know (with the depth and
+    // the move index of each child)
+    final_nodes: Vec<(usize, i32, Vec<i32>)>,
+}
+
+fn open(path: &str) -> BufReader<File> {
+    let f = File::open(path).unwrap_or_else(|e| { log!("{}: {}", path, e); process::exit(1) });
+    BufReader::new(f)
+}
+
+fn lines(path: &str) -> impl Iterator<Item = String> {
+    open(path).lines().map(|l| l.unwrap())
+}
+
+impl Dag {
+    fn new(txt: &str) -> Self {
+        let mut dag = Dag { nodes: vec![], start_nodes: vec![], final_nodes: vec![] };
+        let mut nodes = Nodes::new(open(txt)).unwrap_or_else(|e| { log!("{}: {}", txt, e); process::exit(1) });
+
+        // The initial four nodes
+        let start: Vec<usize> = nodes.start().iter().map(|&n| n as usize).collect();
+
+        let mut any_nodes = vec![]; // "any" node id => "any" node
+        let mut all_nodes = HashMap::new(); // (list of "any" children).sort.uniq => "all" node
+        let mut all_ids = vec![]; // "all" nodes (in order of creation) and their children ids
+        for node in &mut nodes {
+            let node = node.unwrap_or_else(|msg| { log!("{}: {}", txt, msg); process::exit(1) });
+            let id = node.name as usize;
+            let n = dag.add(Node::new(Kind::Any, 1, Some(node.board)));
+            if any_nodes.len() <= id { any_nodes.resize(id + 1, None) }
+            any_nodes[id] = Some(n);
+            let mut idxs = vec![];
+            for (idx, names) in node.moves {
+                let mut ids: Vec<usize> = names.into_iter().map(|n| n as usize).collect();
+                ids.sort();
+                ids.dedup();
+                let c = match all_nodes.get(&ids) {
+                    Some(&c) => c,
+                    None => {
+                        let c = dag.add(Node::new(Kind::All, 0, None));
+                        all_nodes.insert(ids.clone(), c);
+                        all_ids.push((c, ids));
+                        c
+                    }
+                };
+                if !dag.nodes[c].parents.contains(&n) {
+                    dag.nodes[c].parents.push(n);
+                    dag.nodes[n].children.push(c);
+                    idxs.push(idx);
+                }
+            }
+            dag.final_nodes.push((n, node.depth, idxs));
+        }
+
+        dag.start_nodes = start.iter().map(|&i| any_nodes[i].unwrap()).collect();
+
+        // replace all node ids with the reference to the node
+        for (c, ids) in all_ids {
+            let children: Vec<usize> = ids.iter().filter_map(|&i| any_nodes.get(i).cloned().unwrap_or(None)).collect();
+            for &n in &children { dag.nodes[n].parents.push(c) }
+            dag.nodes[c].children = children;
+        }
+
+        // Now, all pairs of parent and child are doubly-linked.
+        dag
+    }
+
+    fn add(&mut self, n: Node) -> usize {
+        self.nodes.push(n);
+        self.nodes.len() - 1
+    }
+
+    // f may change the children of the given node
+    fn each_node<F>(&mut self, mut f: F) where F: FnMut(&mut Dag, usize) {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack = self.start_nodes.clone();
+        while let Some(n) = stack.pop() {
+            if visited[n] { continue }
+            visited[n] = true;
+            f(self, n);
+            stack.extend_from_slice(&self.nodes[n].children);
+        }
+    }
+
+    fn size(&mut self) -> usize {
+        let mut c = 0;
+        self.each_node(|_, _| c += 1);
+        c
+    }
+
+    // A string for a term with a given coefficient
+    fn term(&self, n: usize, weight: usize) -> String {
+        let var = self.nodes[n].var.as_ref().map_or("", |s| s.as_str());
+        if weight >= 2 { format!("{} {}", weight, var) } else { var.to_string() }
+    }
+
+    // A string for a constraint that a node imposes.
+    fn constraint(&self, n: usize) -> String {
+        let node = &self.nodes[n];
+        let weight = if node.kind == Kind::Any { 1 } else { node.children.len() };
+        let lhs: Vec<&str> = node.children.iter().map(|&c| self.nodes[c].var.as_ref().map_or("", |s| s.as_str())).collect();
+        let lhs = lhs.join(" + ");
+        // if this node is pinned, var is always 1
+        if node.pinned { format!("{} >= {}", lhs, weight) } else { format!("{} - {} >= 0", lhs, self.term(n, weight)) }
+    }
+
+    // make a node "pinned"
+    fn pin(&mut self, n: usize, r: &mut Vec<usize>) {
+        if self.nodes[n].pinned { return }
+        r.push(n);
+        self.nodes[n].pinned = true;
+
+        // Now, var of this node is 1.  This affects its parents.  If the parent is
+        // "any", it has to impose no constraint; we remove all children.  If the
+        // parent is "all", just remove this node from its children.
+        for p in self.nodes[n].parents.clone() {
+            if self.nodes[p].kind == Kind::Any {
+                // We mean "no constraints" by setting out-degree to 0.
+                // It's slightly awkward that "any" node has no children, though...
+                self.delete_children(p);
+            }
+            else {
+                self.delete_child(p, n);
+            }
+        }
+
+        // This change also may affects its children.  If this node is "all", or if
+        // it has exactly one child, we must also keep all the children.
+        if self.nodes[n].kind == Kind::All || self.nodes[n].children.len() == 1 {
+            for c in self.nodes[n].children.clone() { self.pin(c, r) }
+        }
+    }
+
+    // merge a node and its child
+    fn merge(&mut self, n: usize) {
+        // we merge only if out-degree(self) = 1 and out-degree(child) = 1
+        while self.nodes[n].children.len() == 1 && self.nodes[self.nodes[n].children[0]].parents.len() == 1 {
+            let c = self.nodes[n].children[0];
+            let children = std::mem::take(&mut self.nodes[c].children);
+            for &cc in &children {
+                self.nodes[cc].parents.retain(|&p| p != c);
+                self.nodes[cc].parents.push(n);
+            }
+
+            // inherit the type and weight of the child
+            self.nodes[n].children = children;
+            self.nodes[n].kind = self.nodes[c].kind;
+            self.nodes[n].weight += self.nodes[c].weight;
+
+            // remove the child from the DAG
+            self.nodes[c].merged = true;
+        }
+    }
+
+    fn delete_children(&mut self, n: usize) {
+        for c in self.nodes[n].children.clone() {
+            self.nodes[c].parents.retain(|&p| p != n);
+            // If any child has no parents, we also remove it (cascade)
+            if self.nodes[c].parents.is_empty() && !self.nodes[c].pinned { self.delete_children(c) }
+        }
+        self.nodes[n].children.clear();
+    }
+
+    fn delete_child(&mut self, n: usize, c: usize) {
+        self.nodes[c].parents.retain(|&p| p != n);
+        self.nodes[n].children.retain(|&c2| c2 != c);
+    }
+
+    fn dump_lp(&mut self, lp: &str) {
+        self.assign_var_names();
+
+        let mut obj = vec![];
+        let mut constraints = vec![];
+        let mut vars = vec![];
+
+        for &n in &self.start_nodes {
+            if !self.nodes[n].pinned { constraints.push(format!("{} = 1", self.term(n, 1))) }
+        }
+        self.each_node(|dag, n| {
+            let node = &dag.nodes[n];
+            if node.weight >= 1 && !node.pinned { obj.push(dag.term(n, node.weight as usize)) }
+            if !node.children.is_empty() { constraints.push(dag.constraint(n)) }
+            if !node.pinned { vars.push(dag.term(n, 1)) }
+        });
+
+        let mut gen = NameGenerator::new();
+        let mut s = String::new();
+        s += "minimize\n";
+        s += &obj.join("\n+ ");
+        s += "\n\n";
+
+        s += "subject to\n";
+        for c in &constraints { s += &format!("{}: {}\n", gen.new_name(), c) }
+        s += "\n";
+
+        s += "binary\n";
+        for v in &vars { s += v; s += "\n" }
+        s += "\n";
+        s += "end\n";
+        write(lp, s);
+        log!("LP size: {}", commas(fs::metadata(lp).unwrap().len()));
+
+        let mut s = String::new();
+        for &(n, _, _) in &self.final_nodes {
+            let node = &self.nodes[n];
+            s += &format!("{:015x} {}\n", node.board.unwrap().0, node.var.as_ref().map_or("", |s| s.as_str()));
+        }
+        write(&format!("{}.map", lp), s);
+    }
+
+    fn assign_var_names(&mut self) {
+        // count the occurences of each variable (in order of first occurence)
+        let mut count = vec![0; self.nodes.len()];
+        let mut order = vec![];
+        self.each_node(|dag, n| {
+            let node = &dag.nodes[n];
+            if node.children.is_empty() { return }
+            let mut inc = |n: usize, c: usize| {
+                if count[n] == 0 { order.push(n) }
+                count[n] += c;
+            };
+            if node.weight > 0 && !node.pinned { inc(n, 2) }
+            for &c in &node.children { inc(c, 1) }
+        });
+
+        // assign variable names in order of frequency
+        // (more frequent variable should have shorter name)
+        let mut gen = NameGenerator::new();
+        order.sort_by_key(|&n| std::cmp::Reverse(count[n]));
+        for n in order { self.nodes[n].var = Some(gen.new_name()) }
+
+        for &(n, _, _) in &self.final_nodes {
+            let node = &mut self.nodes[n];
+            node.var = if node.pinned { Some("(pinned)".to_string()) } else { node.var.take().or(Some("(discarded)".to_string())) };
+        }
+        for &(n, _, _) in &self.final_nodes {
+            if self.nodes[n].merged {
+                let mut m = n;
+                while self.nodes[m].merged { m = self.nodes[m].parents[0] }
+                self.nodes[n].var = self.nodes[m].var.clone();
+            }
+        }
+    }
+
+    // (1) trivial propagation: if there are constraints 'p = 1' and 'c - p >= 0',
+    //     remove them and add a new constraint 'c = 1'.  (We call p is "pinned".)
+    fn pin_true_nodes(&mut self) {
+        let mut r = vec![];
+        for n in self.start_nodes.clone() { self.pin(n, &mut r) }
+        self.start_nodes = r;
+
+        // consisitency check
+        for &n in &self.start_nodes {
+            let node = &self.nodes[n];
+            assert!(node.parents.is_empty());
+            if node.children.is_empty() { continue }
+            assert!(node.children.len() != 1);
+            assert!(!node.children.iter().any(|&c| self.nodes[c].pinned));
+        }
+
+        // remove unused nodes
+        let nodes = &self.nodes;
+        self.start_nodes.retain(|&n| !nodes[n].children.is_empty());
+    }
+
+    // (2) unused constraints: remove a node that has no weight and children.
+    fn remove_unused_leaves(&mut self) {
+        self.each_node(|dag, n| {
+            if dag.nodes[n].weight == 0 && dag.nodes[n].children.is_empty() { dag.pin(n, &mut vec![]) }
+        });
+    }
+
+    // (3) trivial merge: if there are `c1 - p >= 0' and `c2 - c1 >= 0`, remove
+    //     them and add a new constraint 'c2 - p >= 0'.
+    fn merge_exclusive_node_pairs(&mut self) {
+        self.each_node(|dag, n| dag.merge(n));
+    }
+
+    // reverse the solution (variable assignment) to boards we must keep, their
+    // depths, and move index to choose.
+    fn invert_solution(&mut self, sol: &str, map: &str, format: Format) {
+        let mut board2node = BoardMap::new();
+        for &(n, _, _) in &self.final_nodes { board2node[self.nodes[n].board.unwrap()] = n as i32 }
+
+        // "var 1 (obj:N)"
+        let mut var2value = HashSet::new();
+        for l in lines(sol) {
+            let fields: Vec<&str> = l.split_whitespace().collect();
+            let obj = fields.len() == 3 && fields[2].len() > 6 && fields[2].starts_with("(obj:") && fields[2].ends_with(')') &&
+                      fields[2][5..fields[2].len() - 1].bytes().all(|c| c.is_ascii_digit());
+            if obj && fields[1] == "1" && !l.starts_with(char::is_whitespace) {
+                var2value.insert(fields[0].to_string());
+            }
+            else {
+                log!("{}", l);
+            }
+        }
+
+        for l in lines(map) {
+            let mut split = l.split_whitespace();
+            let board = Board(u64::from_str_radix(split.next().unwrap(), 16).unwrap());
+            assert!(board2node.contains(board));
+            let n = board2node[board] as usize;
+            self.nodes[n].value = match split.next() {
+                Some("(pinned)") => true,
+                Some("(discarded)") => false,
+                Some(var) => var2value.contains(var),
+                None => false,
+            };
+        }
+
+        let mut boards = vec![];
+        for &(n, depth, ref idxs) in &self.final_nodes {
+            let node = &self.nodes[n];
+            if !node.value { continue }
+            let i = node.children.iter().position(|&c| {
+                self.nodes[c].children.iter().all(|&cc| self.nodes[cc].value)
+            }).expect("no move is chosen");
+            boards.push((node.board.unwrap(), depth, idxs[i]));
+        }
+
+        let header = Header { stage: 4, order: Order::Unsorted, idx: true, count: Some(boards.len() as u64) };
+        let mut out = Out::records(format, header);
+        for &(b, depth, idx) in &boards { out.record_idx(b, depth, idx) }
+        log!("#boards: {}", boards.len());
+    }
+
+    fn optimize(&mut self) {
+        log!("#node: {}", self.size());
+
+        log!("removing unused leaves...");
+        self.remove_unused_leaves();
+        log!("#node: {}", self.size());
+
+        log!("pinning true nodes...");
+        self.pin_true_nodes();
+        log!("#node: {}", self.size());
+
+        log!("removing unused leaves (again)...");
+        self.remove_unused_leaves();
+        log!("#node: {}", self.size());
+
+        log!("merging exclusive node pairs...");
+        self.merge_exclusive_node_pairs();
+        log!("#node: {}", self.size());
+    }
+}
+
+// short names of LP variables and constraints
+struct NameGenerator {
+    n: usize,
+    alpha: Vec<u8>,
+    alnum: Vec<u8>,
+}
+
+impl NameGenerator {
+    fn new() -> Self {
+        // Note that "e01" is float
+        let alpha: Vec<u8> = (b'A'..b'Z' + 1).chain(b'a'..b'z' + 1).chain(Some(b'_')).filter(|&c| c != b'E' && c != b'e').collect();
+        let mut alnum = alpha.clone();
+        alnum.extend(b'0'..b'9' + 1);
+        alnum.extend(b"Ee!\"#$%&(),.;?@{}~".iter());
+        alnum.sort();
+        NameGenerator { n: 0, alpha, alnum }
+    }
+
+    fn new_name(&mut self) -> String {
+        const RESERVED : [&'static str; 11] = ["GEN", "SOS", "BIN", "END", "INT", "INF", "NAN", "ST", "MIN", "MAX", "OBJ"];
+        loop {
+            let mut n = self.n;
+            let mut s = vec![self.alpha[n % self.alpha.len()]];
+            n /= self.alpha.len();
+            while n != 0 {
+                s.push(self.alnum[n % self.alnum.len()]);
+                n /= self.alnum.len();
+            }
+            self.n += 1;
+            let s = String::from_utf8(s).unwrap();
+            let upper = s.to_ascii_uppercase();
+            if !RESERVED.iter().any(|r| upper.contains(r)) { return s }
+        }
+    }
+}
+
+fn write(path: &str, s: String) {
+    fs::write(path, s).unwrap_or_else(|e| { log!("{}: {}", path, e); process::exit(1) })
+}
+
+// 1234567 => "1,234,567"
+fn commas(n: u64) -> String {
+    let s = n.to_string();
+    let mut r = String::new();
+    for (i, c) in s.chars().enumerate() {
+        if i > 0 && (s.len() - i) % 3 == 0 { r.push(',') }
+        r.push(c);
+    }
+    r
+}
+
+fn main() {
+    let format = Format::from_args();
+    let mut args = vec![];
+    let mut iter = env::args();
+    while let Some(arg) = iter.next() {
+        if arg == "--format" { iter.next(); } else { args.push(arg) }
+    }
+    if args.len() < 3 {
+        log!("usage:");
+        log!("  4-opt 3.txt 4.lp");
+        log!("  4-opt [--format text|binary] 3.txt 4.sol 4.lp.map");
+        process::exit(1)
+    }
+
+    log!("Step 4: minimize the DAG");
+
+    let mut dag = Dag::new(&args[1]);
+    if args[2].ends_with(".lp") {
+        dag.optimize();
+        dag.dump_lp(&args[2]);
+    }
+    else {
+        if args.len() < 4 { log!("missing 4.lp.map"); process::exit(1) }
+        dag.invert_solution(&args[2], &args[3], format);
+    }
+
+    log!("Step 4: done!");
+}
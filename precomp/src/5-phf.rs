@@ -0,0 +1,55 @@
+// Make perfect hash function (PHF) for the data base, mapping from board to
+// (depth, move index).  See phf.rs for the details.
+//
+// Input: 4-opt's output (in either format)
+//
+// Output:
+//   [[p1, p2, p3], [slot, ...]]
+//
+//   p1, p2, p3: the seed primes
+//   slot: value * 3 + assignment (value: (depth - 5) / 2 * 34 + idx)
+//
+// `--format binary` writes the same table in binary (see Phf::write).
+
+#[macro_use]
+extern crate precomp;
+
+use std::env;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use precomp::{Format, In};
+use precomp::board_collection::BoardMap;
+use precomp::phf::{self, MIN_DEPTH, MAX_DEPTH, MAX_IDX};
+
+fn main() {
+    log!("Step 5: make perfect hash function");
+
+    let format = Format::from_args();
+    // load the list of board, depth, and move index
+    let mut map = BoardMap::new();
+    let mut boards = vec![];
+    let check = env::args().skip(1).any(|a| a == "--check");
+    In::each(check, |b, depth, idx| {
+        if !(MIN_DEPTH..=MAX_DEPTH).contains(&depth) || idx >= MAX_IDX {
+            log!("out of range: {:015x} {} {}", b.0, depth, idx);
+            process::exit(1)
+        }
+        let v = phf::pack(depth, idx);
+        let i = *map.entry(b).or_insert(boards.len());
+        if i < boards.len() { boards[i] = (b, v) } else { boards.push((b, v)) }
+    });
+    log!("boards: {}", boards.len());
+
+    let phf = match phf::build(&boards) {
+        Some(phf) => phf,
+        None => { log!("no seed primes found"); process::exit(1) }
+    };
+    log!("seed primes: {:?}", phf.ps);
+    log!("table size: {} (load factor: {:.3})", phf.table.len(), phf.load_factor(boards.len()));
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    phf.write(&mut out, format).and_then(|_| out.flush()).expect("failed printing to stdout");
+
+    log!("Step 5: done!");
+}
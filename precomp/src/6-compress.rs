@@ -0,0 +1,39 @@
+// Compress and encode the data base as a text, by range coder.  See
+// compress.rs for the format.
+//
+// Input: 5-phf's output (in either format)
+//
+// Output: the encoded data base (ai.txt, which client/src/ai.ts decodes)
+
+#[macro_use]
+extern crate precomp;
+
+use std::io;
+use std::process;
+use precomp::Out;
+use precomp::compress;
+use precomp::phf::Phf;
+
+fn main() {
+    log!("Step 6: compress the data base");
+
+    let stdin = io::stdin();
+    let phf = match Phf::read(stdin.lock()) {
+        Ok(phf) => phf,
+        Err(e) => { log!("invalid input: {}", e); process::exit(1) }
+    };
+
+    let txt = compress::encode(&phf);
+    log!("size: {} -> {}", phf.table.len(), txt.len());
+
+    // decoding test
+    match compress::decode(&txt) {
+        Some(ref phf2) if phf2.ps == phf.ps && phf2.table == phf.table => {}
+        _ => { log!("decoding test failed"); process::exit(1) }
+    }
+
+    let mut out = Out::new();
+    out!(out, "{}", txt);
+
+    log!("Step 6: done!");
+}
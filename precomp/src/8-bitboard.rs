@@ -0,0 +1,37 @@
+// Check if the table-driven move generator (bitboard.rs) agrees with the
+// reference implementation in board.rs.
+//
+// Input: 1-enum's output (in either format)
+//
+// Output: none
+
+#[macro_use]
+extern crate precomp;
+
+use std::env;
+use std::process;
+use precomp::In;
+
+fn main() {
+    log!("Step 8: check the bitboard move generator");
+
+    let mut count = 0;
+    let check = env::args().skip(1).any(|a| a == "--check");
+    In::each(check, |b, _, _| {
+        let mut bs1 = vec![];
+        let mut bs2 = vec![];
+        let s1 = b.each_next(|nb| { bs1.push(nb); true });
+        let s2 = b.each_next_naive(|nb| { bs2.push(nb); true });
+        if s1 != s2 || bs1 != bs2 || b.reverse() != b.reverse_naive() || b.prev() != b.prev_naive() {
+            log!("mismatch!: {:015x}", b.0);
+            process::exit(1)
+        }
+        count += 1;
+        if count % 10000000 == 0 {
+            log!("checking... (boards: {})", count);
+        }
+    });
+
+    log!("  checked boards: {}", count);
+    log!("Step 8: done!");
+}
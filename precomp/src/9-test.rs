@@ -7,13 +7,17 @@
 //   board: hex representation of bit-board
 //   depth: the depth of the board
 //   idx: move index (the index of return value of Board#next)
+//
+// Output: the boards visited by the check (one per line, or binary records
+// with depth -1 by `--format binary`)
 
 #[macro_use]
 extern crate precomp;
 
+use std::env;
 use std::process;
-use precomp::{In, Out};
-use precomp::board::{Board, Result};
+use precomp::{Format, Header, In, Order, Out};
+use precomp::board::{Board, Status};
 use precomp::board_collection::{BoardSet, BoardMap};
 
 struct Node {
@@ -22,14 +26,14 @@ struct Node {
 }
 
 // load all boards
-fn load() -> (Vec<Node>, BoardMap) {
+fn load(check: bool) -> (Vec<Node>, BoardMap<usize>) {
     let mut map = BoardMap::new();
     let mut nodes = vec![];
-    In::each(|b, depth, idx| {
-        map[b] = nodes.len() as i32;
+    In::each(check, |b, depth, idx| {
+        map[b] = nodes.len();
         nodes.push(Node {
             idx: idx as u8,
-            depth: depth,
+            depth,
         })
     });
     log!("board#: {}", nodes.len());
@@ -44,14 +48,14 @@ macro_rules! error(
 );
 
 fn main() {
-    let mut out = Out::new();
+    let format = Format::from_args();
+    let mut out = Out::records(format, Header { stage: 9, order: Order::Unsorted, idx: false, count: None });
 
-    let (nodes, map) = load();
+    let check = env::args().skip(1).any(|a| a == "--check");
+    let (nodes, map) = load(check);
 
     let mut boards = vec![];
-    if let Result::Unknown(bs) = Board::init().next() {
-        for b in bs { boards.push((b, 78)) }
-    }
+    Board::init().each_next(|b| { boards.push((b, 78)); true });
 
     let mut visited = BoardSet::new();
 
@@ -60,27 +64,35 @@ fn main() {
         if visited.contains(b) { continue }
         visited.insert(b);
 
-        out!(out, "{:015x}\n", b.0);
+        match format {
+            Format::Text => out!(out, "{:015x}\n", b.0),
+            Format::Binary => out.record(b, -1),
+        }
 
         if b.easy() { continue }
 
-        if !map.contains(b) {
-            error!("unknown board!: {:015x}", b.0);
-        }
-
-        let ref node = nodes[map[b] as usize];
+        let node = match map.get(b) {
+            Some(&i) => &nodes[i],
+            None => error!("unknown board!: {:015x}", b.0),
+        };
 
         if node.depth >= depth {
             error!("error! board={:015x} depth={} (expected: <{})\n",
                 b.0, depth, depth);
         }
 
-        match b.next() {
-            Result::Win | Result::Lose => error!("error!"),
-            Result::Unknown(bs) =>
-                if let Result::Unknown(bs) = bs[node.idx as usize].next() {
-                    for b in bs { boards.push((b, node.depth)) }
-                }
+        let mut i = 0;
+        let mut nb = None;
+        let s = b.each_next(|b| {
+            if i == node.idx { nb = Some(b) }
+            i += 1;
+            nb.is_none()
+        });
+        match (s, nb) {
+            (Status::Unknown, Some(nb)) => {
+                nb.each_next(|b| { boards.push((b, node.depth)); true });
+            }
+            _ => error!("error!"),
         }
     }
     log!("OK!")
@@ -0,0 +1,221 @@
+// a table-driven move generator that works directly on the packed u64
+//
+// A cell (x, y) is the nibble i = x * 4 + y of a board.  Occupancy is kept as
+// a mask that has the lowest bit of each occupied nibble (e.g., 1 << (i * 4)),
+// so it can be calculated from the packed u64 without any loop.  The masks
+// are not stored in Board: a board is a bare u64 that is hashed and written
+// out everywhere, and computing a mask takes a few instructions.
+//
+// The destinations (and the sources, for prev) of each piece are listed in
+// the order of Dir (see board.rs), and the cells are scanned in the same
+// order as Board::next and Board::prev, so the boards are enumerated in
+// exactly the same order as the reference implementations.
+
+use board::{Board, Status};
+
+const CELLS   : u64 = 0x0000ffffffffffffu64;
+const NIBBLES : u64 = 0x0000111111111111u64; // the lowest bit of each cell
+const ROW0    : u64 = 0x0000000100010001u64; // the cells of y = 0
+
+const LION : u64 = 1;
+const CHICK : u64 = 4;
+const HEN : u64 = 5;
+const OPPONENT_LION : u64 = 9;
+
+// cells in the order of Board::next (y-major)
+const ORDER : [u64; 12] = [0, 4, 8, 1, 5, 9, 2, 6, 10, 3, 7, 11];
+
+// the move directions of each piece, in the order of Dir
+const DIRS : [&'static [(i8, i8)]; 6] = [
+    &[],
+    &[(-1, 1), (0, 1), (1, 1), (-1, 0), (1, 0), (-1, -1), (0, -1), (1, -1)],
+    &[(-1, 1),         (1, 1),                   (-1, -1),          (1, -1)],
+    &[         (0, 1),         (-1, 0), (1, 0),            (0, -1)         ],
+    &[         (0, 1)                                                      ],
+    &[(-1, 1), (0, 1), (1, 1), (-1, 0), (1, 0),            (0, -1)         ],
+];
+
+// the destination cells of a piece at a cell
+#[derive(Clone, Copy)]
+struct Dests {
+    len: usize,
+    cells: [u64; 8],
+}
+
+// the destination cells of each piece at each cell
+static DESTS : [[Dests; 12]; 6] = dests(1);
+
+// the source cells of each piece that moves to each cell
+static SOURCES : [[Dests; 12]; 6] = dests(-1);
+
+// the cells from which each piece can move to each cell (as a mask)
+static ATTACKERS : [[u64; 12]; 6] = attackers();
+
+// sign: 1 for the destinations, -1 for the sources
+const fn dests(sign: i8) -> [[Dests; 12]; 6] {
+    const EMPTY : Dests = Dests { len: 0, cells: [0; 8] };
+    let mut t = [[EMPTY; 12]; 6];
+    let mut p = 1;
+    while p < 6 {
+        let mut i = 0;
+        while i < 12 {
+            let (x, y) = ((i / 4) as i8, (i % 4) as i8);
+            let mut k = 0;
+            while k < DIRS[p].len() {
+                let (nx, ny) = (x + sign * DIRS[p][k].0, y + sign * DIRS[p][k].1);
+                if 0 <= nx && nx <= 2 && 0 <= ny && ny <= 3 {
+                    t[p][i].cells[t[p][i].len] = (nx * 4 + ny) as u64;
+                    t[p][i].len += 1;
+                }
+                k += 1;
+            }
+            i += 1;
+        }
+        p += 1;
+    }
+    t
+}
+
+const fn attackers() -> [[u64; 12]; 6] {
+    let d = dests(1);
+    let mut t = [[0; 12]; 6];
+    let mut p = 1;
+    while p < 6 {
+        let mut i = 0;
+        while i < 12 {
+            let mut k = 0;
+            while k < d[p][i].len {
+                t[p][d[p][i].cells[k] as usize] |= 1 << (i * 4);
+                k += 1;
+            }
+            i += 1;
+        }
+        p += 1;
+    }
+    t
+}
+
+// a mask of cells that have a given piece
+#[inline]
+fn cells_of(c: u64, p: u64) -> u64 {
+    let d = c ^ (p * NIBBLES);
+    !(d | d >> 1 | d >> 2 | d >> 3) & NIBBLES
+}
+
+// a mask of cells that have any piece
+#[inline]
+fn occupied(c: u64) -> u64 {
+    (c | c >> 1 | c >> 2 | c >> 3) & NIBBLES
+}
+
+// a mask of cells that have the player's pieces
+#[inline]
+fn mine(c: u64) -> u64 {
+    (c | c >> 1 | c >> 2) & !(c >> 3) & NIBBLES
+}
+
+pub fn reverse(b: Board) -> Board {
+    let c = b.0 & CELLS;
+    // swap y = 0 <=> 3 and y = 1 <=> 2 in each column
+    let c =
+        ((c & 0x000f000f000fu64) << 12) |
+        ((c & 0x00f000f000f0u64) <<  4) |
+        ((c & 0x0f000f000f00u64) >>  4) |
+        ((c & 0xf000f000f000u64) >> 12);
+    // swap black and white
+    let c = c ^ (((c | c >> 1 | c >> 2) & NIBBLES) << 3);
+    Board(c |
+        ((b.0 & 0xfc0000000000000u64) >> 6) |
+        ((b.0 & 0x03f000000000000u64) << 6))
+}
+
+pub fn status(b: Board) -> Status {
+    let c = b.0 & CELLS;
+    let lion = cells_of(c, OPPONENT_LION);
+    if lion == 0 { return Status::Unknown }
+    let l = (lion.trailing_zeros() / 4) as usize;
+    if (1..6).any(|p| cells_of(c, p as u64) & ATTACKERS[p][l] != 0) { return Status::Win }
+    if lion & ROW0 != 0 { Status::Lose } else { Status::Unknown }
+}
+
+#[inline]
+pub fn each_next<F>(b: Board, mut f: F) -> Status where F: FnMut(Board) -> bool {
+    let s = status(b);
+    if s != Status::Unknown { return s }
+
+    #[inline]
+    fn finish(b: u64) -> Board {
+        reverse(Board(b)).normalize()
+    }
+
+    let c = b.0 & CELLS;
+    let mine = mine(c);
+    for &i in ORDER.iter() {
+        let p = (c >> (i * 4)) & 0xf;
+        if p == 0 {
+            // drop a piece in hand
+            for k in 2..5 {
+                if (b.0 >> (44 + k * 2)) & 3 != 0 && !f(finish((b.0 | k << (i * 4)) - (1 << (44 + k * 2)))) {
+                    return s
+                }
+            }
+        }
+        else if p < 8 {
+            let b = b.0 & !(0xf << (i * 4));
+            let d = &DESTS[p as usize][i as usize];
+            for &j in &d.cells[..d.len] {
+                if mine & (1 << (j * 4)) != 0 { continue }
+                let q = (c >> (j * 4)) & 0xf;
+                let b = if q == 0 { b } else {
+                    // capture (a hen becomes a chick)
+                    let k = if q & 7 == HEN { CHICK } else { q & 7 };
+                    (b & !(0xf << (j * 4))) + (1 << (44 + k * 2))
+                };
+                let p = if p == CHICK && j % 4 == 3 { HEN } else { p };
+                if !f(finish(b | p << (j * 4))) { return s }
+            }
+        }
+    }
+    s
+}
+
+pub fn prev(b: Board) -> Vec<Board> {
+    // move p back from i to j (and put back a piece captured at i)
+    fn move_backward(boards: &mut Vec<Board>, b: u64, i: u64, j: u64, p: u64) {
+        let nb = b | p << (j * 4);
+        boards.push(Board(nb));
+        for k in 2..5 {
+            if (b >> (44 + k * 2)) & 3 != 0 {
+                let nb = nb - (1 << (44 + k * 2));
+                boards.push(Board(nb | (k | 8) << (i * 4)));
+                if k == CHICK { boards.push(Board(nb | (HEN | 8) << (i * 4))) }
+            }
+        }
+    }
+
+    let b = reverse(b).0;
+    let c = b & CELLS;
+    let occupied = occupied(c);
+    let mut boards = vec![];
+    for &i in ORDER.iter() {
+        let p = (c >> (i * 4)) & 0xf;
+        if p == 0 || p >= 8 { continue }
+        let b = b & !(0xf << (i * 4));
+        // a chick on the far rank must have been dropped
+        // (a chick that moves there is promoted)
+        if p != CHICK || i % 4 != 3 {
+            let s = &SOURCES[p as usize][i as usize];
+            for &j in &s.cells[..s.len] {
+                if occupied & (1 << (j * 4)) == 0 { move_backward(&mut boards, b, i, j, p) }
+            }
+        }
+        if p == HEN && i % 4 == 3 && occupied & (1 << ((i - 1) * 4)) == 0 {
+            move_backward(&mut boards, b, i, i - 1, CHICK);
+        }
+        if p != LION && p != HEN { boards.push(Board(b + (1 << (44 + p * 2)))) }
+    }
+    for b in boards.iter_mut() {
+        *b = b.normalize()
+    }
+    boards
+}
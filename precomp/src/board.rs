@@ -1,17 +1,42 @@
 use std::fmt;
+use bitboard;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Board(pub u64);
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Piece(u8);
 
 #[derive(PartialEq, Eq)]
-pub struct Move(i8, i8);
+pub struct Dir(i8, i8);
 
-#[derive(PartialEq, Eq)]
+// a move from the player's point of view
+//   (x, y): the original cell (None for a drop)
+//   (nx, ny): the destination cell
+//   piece: the moved piece (before promotion)
+//   capture: the captured piece (EMPTY if nothing is captured)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Move {
+    pub from: Option<(i8, i8)>,
+    pub to: (i8, i8),
+    pub piece: Piece,
+    pub capture: Piece,
+    pub promotion: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Color { Black, White }
+
+#[derive(PartialEq, Eq, Debug)]
 pub enum Result { Win, Lose, Unknown(Vec<Board>) }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Status { Win, Lose, Unknown }
+
+// the reason why Board::validate rejects a board
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InvalidBoard(String);
+
 pub const EMPTY    : Piece = Piece(0);
 pub const LION     : Piece = Piece(1);
 pub const ELEPHANT : Piece = Piece(2);
@@ -19,21 +44,21 @@ pub const GIRAFFE  : Piece = Piece(3);
 pub const CHICK    : Piece = Piece(4);
 pub const HEN      : Piece = Piece(5);
 
-const MOVE_NW : &'static Move = &Move(-1,  1);
-const MOVE_N  : &'static Move = &Move( 0,  1);
-const MOVE_NE : &'static Move = &Move( 1,  1);
-const MOVE_W  : &'static Move = &Move(-1,  0);
-const MOVE_E  : &'static Move = &Move( 1,  0);
-const MOVE_SW : &'static Move = &Move(-1, -1);
-const MOVE_S  : &'static Move = &Move( 0, -1);
-const MOVE_SE : &'static Move = &Move( 1, -1);
-
-const MOVE_DUMMY    : &'static [&'static Move] = &[];
-const MOVE_LION     : &'static [&'static Move] = &[MOVE_NW, MOVE_N, MOVE_NE, MOVE_W, MOVE_E, MOVE_SW, MOVE_S, MOVE_SE];
-const MOVE_ELEPHANT : &'static [&'static Move] = &[MOVE_NW,         MOVE_NE,                 MOVE_SW,         MOVE_SE];
-const MOVE_GIRAFFE  : &'static [&'static Move] = &[         MOVE_N,          MOVE_W, MOVE_E,          MOVE_S         ];
-const MOVE_CHICK    : &'static [&'static Move] = &[         MOVE_N                                                   ];
-const MOVE_HEN      : &'static [&'static Move] = &[MOVE_NW, MOVE_N, MOVE_NE, MOVE_W, MOVE_E,          MOVE_S         ];
+const MOVE_NW : &'static Dir = &Dir(-1,  1);
+const MOVE_N  : &'static Dir = &Dir( 0,  1);
+const MOVE_NE : &'static Dir = &Dir( 1,  1);
+const MOVE_W  : &'static Dir = &Dir(-1,  0);
+const MOVE_E  : &'static Dir = &Dir( 1,  0);
+const MOVE_SW : &'static Dir = &Dir(-1, -1);
+const MOVE_S  : &'static Dir = &Dir( 0, -1);
+const MOVE_SE : &'static Dir = &Dir( 1, -1);
+
+const MOVE_DUMMY    : &'static [&'static Dir] = &[];
+const MOVE_LION     : &'static [&'static Dir] = &[MOVE_NW, MOVE_N, MOVE_NE, MOVE_W, MOVE_E, MOVE_SW, MOVE_S, MOVE_SE];
+const MOVE_ELEPHANT : &'static [&'static Dir] = &[MOVE_NW,         MOVE_NE,                 MOVE_SW,         MOVE_SE];
+const MOVE_GIRAFFE  : &'static [&'static Dir] = &[         MOVE_N,          MOVE_W, MOVE_E,          MOVE_S         ];
+const MOVE_CHICK    : &'static [&'static Dir] = &[         MOVE_N                                                   ];
+const MOVE_HEN      : &'static [&'static Dir] = &[MOVE_NW, MOVE_N, MOVE_NE, MOVE_W, MOVE_E,          MOVE_S         ];
 
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -41,17 +66,32 @@ impl fmt::Display for Board {
     }
 }
 
-impl fmt::Display for Move {
+impl fmt::Display for InvalidBoard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for Dir {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Move({}, {})", self.0, self.1)
+        write!(f, "Dir({}, {})", self.0, self.1)
+    }
+}
+
+impl Color {
+    pub fn opponent(&self) -> Color {
+        match *self {
+            Color::Black => Color::White,
+            Color::White => Color::Black,
+        }
     }
 }
 
 impl Piece {
-    fn opponent(&self) -> Piece {
+    pub fn opponent(&self) -> Piece {
         Piece(self.0 ^ 8)
     }
-    fn show(&self) -> char {
+    pub fn show(&self) -> char {
         match self.0 {
             0 => '.',
             1 => 'L',  2 => 'E',  3 => 'G',  4 => 'C',  5 => 'H',
@@ -59,7 +99,7 @@ impl Piece {
             _ => '*'
         }
     }
-    fn moves(&self) -> &'static [&'static Move] {
+    fn moves(&self) -> &'static [&'static Dir] {
         match *self {
             LION     => MOVE_LION,
             ELEPHANT => MOVE_ELEPHANT,
@@ -69,11 +109,8 @@ impl Piece {
             _ => MOVE_DUMMY,
         }
     }
-    fn mine(&self) -> bool {
-        match self.0 {
-            1 | 2 | 3 | 4 | 5 => true,
-            _ => false
-        }
+    pub fn mine(&self) -> bool {
+        matches!(self.0, 1..=5)
     }
 }
 
@@ -90,8 +127,11 @@ impl Board {
         Board(self.0 & !(0xf << ((x * 4 + y) * 4)))
     }
 
+    // the number of p in hand: the player's hands are at bits 48-53 and the
+    // opponent's at bits 54-59 (2 bits per piece: elephant, giraffe, chick),
+    // so an opponent piece (p.0 = 8 + n) is at 34 + p.0 * 2 = 50 + n * 2
     pub fn hand(&self, p: Piece) -> i8 {
-        ((self.0 >> ((if p.0 < 8 { 44 } else { 36 }) + p.0 * 2)) & 3) as i8
+        ((self.0 >> ((if p.0 < 8 { 44 } else { 34 }) + p.0 * 2)) & 3) as i8
     }
 
     pub fn inc_hand(&self, p: Piece) -> Board {
@@ -106,15 +146,25 @@ impl Board {
     }
 
     pub fn normalize(&self) -> Board {
-        let b =
+        let b = self.flip();
+        if self.0 < b.0 { *self } else { b }
+    }
+
+    // left <=> right
+    pub fn flip(&self) -> Board {
+        Board(
             ((self.0 & 0xffff00000000u64) >> 32) |
             ( self.0 & 0x0000ffff0000u64       ) |
             ((self.0 & 0x00000000ffffu64) << 32) |
-            (self.0 & 0xfff000000000000u64);
-        Board(if self.0 < b { self.0 } else { b })
+            (self.0 & 0xfff000000000000u64))
     }
 
     pub fn reverse(&self) -> Board {
+        bitboard::reverse(*self)
+    }
+
+    // the reference implementation of reverse
+    pub fn reverse_naive(&self) -> Board {
         let mut b = Board(
             ((self.0 & 0xfc0000000000000u64) >> 6) |
             ((self.0 & 0x03f000000000000u64) << 6));
@@ -129,6 +179,7 @@ impl Board {
         b
     }
 
+    #[allow(clippy::let_and_return)] // keep the pieces aligned
     pub fn init() -> Board {
         let b = Board(0);
         let b = b.put(0, 0, GIRAFFE );
@@ -142,16 +193,57 @@ impl Board {
         b
     }
 
+    // check the invariants of the bit layout:
+    //   - no bits other than the cells and the hands
+    //   - each cell has a valid piece
+    //   - one lion per side
+    //   - at most two of each other kind (counting hands; a hen is a chick)
+    //
+    // Note that a chick on the far rank is valid because it can be dropped
+    // there (it cannot move any more, though).
+    pub fn validate(&self) -> ::std::result::Result<(), InvalidBoard> {
+        macro_rules! fail(
+            ($($arg:tt)*) => { return Err(InvalidBoard(format!($($arg)*))) }
+        );
+
+        if self.0 >> 60 != 0 { fail!("unused bits are set") }
+
+        // the number of pieces of each kind (the lions are counted per side)
+        let mut lions = [0; 2];
+        let mut count = [0; 5];
+        for y in 0..4 {
+            for x in 0..3 {
+                let p = self.get(x, y);
+                match p.0 {
+                    0 => {}
+                    1 | 9 => lions[(p.0 >> 3) as usize] += 1,
+                    2..=4 | 10..=12 => count[(p.0 & 7) as usize] += 1,
+                    5 | 13 => count[CHICK.0 as usize] += 1,
+                    _ => fail!("unknown piece {} at {}{}", p.0, "CBA".as_bytes()[x as usize] as char, 4 - y),
+                }
+            }
+        }
+        if lions[0] != 1 { fail!("{} lions of the player", lions[0]) }
+        if lions[1] != 1 { fail!("{} lions of the opponent", lions[1]) }
+        for &(p, name) in [(ELEPHANT, "elephants"), (GIRAFFE, "giraffes"), (CHICK, "chicks")].iter() {
+            let (h1, h2) = (self.hand(p), self.hand(p.opponent()));
+            if h1 > 2 || h2 > 2 { fail!("{} {} in hand", if h1 > 2 { h1 } else { h2 }, name) }
+            let n = count[p.0 as usize] + h1 + h2;
+            if n > 2 { fail!("{} {}", n, name) }
+        }
+        Ok(())
+    }
+
     pub fn show(&self) {
         println!("---");
         for y in (0..4).rev() {
             let mut s : String = (0..3).rev().map(|x| self.get(x, y).show()).collect();
             if y == 0 || y == 3 {
-                s = s + " (";
+                s += " (";
                 for p in [ELEPHANT, GIRAFFE, CHICK].iter() {
                     for _ in 0..self.hand(if y == 3 { p.opponent() } else { *p }) { s.push(p.show()) }
                 }
-                s = s + ")";
+                s += ")";
             }
             println!("{}", s)
         }
@@ -159,6 +251,48 @@ impl Board {
 
     pub fn next(&self) -> Result {
         let mut boards = vec![];
+        match self.each_next(|b| { boards.push(b); true }) {
+            Status::Win => Result::Win,
+            Status::Lose => Result::Lose,
+            Status::Unknown => Result::Unknown(boards),
+        }
+    }
+
+    // check if the player can capture the opponent's lion (Win), or the
+    // opponent's lion has succeeded "try" (Lose)
+    pub fn status(&self) -> Status {
+        bitboard::status(*self)
+    }
+
+    // same as next(), but calls f for each next board instead of allocating
+    // (f is called only when Unknown is returned, and may return false to stop)
+    #[inline]
+    pub fn each_next<F>(&self, f: F) -> Status where F: FnMut(Board) -> bool {
+        bitboard::each_next(*self, f)
+    }
+
+    // the reference implementation of status (see also bitboard.rs)
+    pub fn status_naive(&self) -> Status {
+        for y in 0..4 {
+            for x in 0..3 {
+                if self.get(x, y) != LION.opponent() { continue }
+                for m in MOVE_LION {
+                    let px = x - m.0;
+                    if !(0..=2).contains(&px) { continue }
+                    let py = y - m.1;
+                    if !(0..=3).contains(&py) { continue }
+                    if self.get(px, py).moves().contains(m) { return Status::Win }
+                }
+                if y == 0 { return Status::Lose }
+            }
+        }
+        Status::Unknown
+    }
+
+    // the reference implementation of each_next (see also bitboard.rs)
+    pub fn each_next_naive<F>(&self, mut f: F) -> Status where F: FnMut(Board) -> bool {
+        let s = self.status_naive();
+        if s != Status::Unknown { return s }
         for y in 0..4 {
             for x in 0..3 {
                 let p = self.get(x, y);
@@ -167,46 +301,96 @@ impl Board {
                         let b = self.del(x, y);
                         for m in p.moves() {
                             let nx = x + m.0;
-                            if nx < 0 || 2 < nx { continue }
+                            if !(0..=2).contains(&nx) { continue }
                             let ny = y + m.1;
-                            if ny < 0 || 3 < ny { continue }
+                            if !(0..=3).contains(&ny) { continue }
                             let np = b.get(nx, ny);
                             if np.mine() { continue }
-                            if np == LION.opponent() { return Result::Win }
                             let b = if np == EMPTY { b } else { b.del(nx, ny).inc_hand(np.opponent()) };
                             let b = b.put(nx, ny, if p == CHICK && ny == 3 { HEN } else { p });
-                            boards.push(b)
+                            if !f(b.reverse_naive().normalize()) { return s }
                         }
                     },
                     EMPTY => {
-                        if self.hand(ELEPHANT) > 0 {
-                            boards.push(self.put(x, y, ELEPHANT).dec_hand(ELEPHANT))
-                        }
-                        if self.hand(GIRAFFE) > 0 {
-                            boards.push(self.put(x, y, GIRAFFE).dec_hand(GIRAFFE))
+                        if self.hand(ELEPHANT) > 0 && !f(self.put(x, y, ELEPHANT).dec_hand(ELEPHANT).reverse_naive().normalize()) { return s }
+                        if self.hand(GIRAFFE) > 0 && !f(self.put(x, y, GIRAFFE).dec_hand(GIRAFFE).reverse_naive().normalize()) { return s }
+                        if self.hand(CHICK) > 0 && !f(self.put(x, y, CHICK).dec_hand(CHICK).reverse_naive().normalize()) { return s }
+                    },
+                    _ => ()
+                }
+            }
+        }
+        s
+    }
+
+    // list all moves in the same order as the boards of next()
+    // (empty if the opponent has already succeeded "try")
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = vec![];
+        let mut win = false;
+        for y in 0..4 {
+            for x in 0..3 {
+                let p = self.get(x, y);
+                match p {
+                    LION | ELEPHANT | GIRAFFE | CHICK | HEN => {
+                        for m in p.moves() {
+                            let nx = x + m.0;
+                            if !(0..=2).contains(&nx) { continue }
+                            let ny = y + m.1;
+                            if !(0..=3).contains(&ny) { continue }
+                            let np = self.get(nx, ny);
+                            if np.mine() { continue }
+                            if np == LION.opponent() { win = true }
+                            moves.push(Move {
+                                from: Some((x, y)),
+                                to: (nx, ny),
+                                piece: p,
+                                capture: np,
+                                promotion: p == CHICK && ny == 3,
+                            })
                         }
-                        if self.hand(CHICK) > 0 {
-                            boards.push(self.put(x, y, CHICK).dec_hand(CHICK))
+                    },
+                    EMPTY => {
+                        for &p in [ELEPHANT, GIRAFFE, CHICK].iter() {
+                            if self.hand(p) > 0 {
+                                moves.push(Move { from: None, to: (x, y), piece: p, capture: EMPTY, promotion: false })
+                            }
                         }
                     },
                     _ => ()
                 }
             }
         }
-        for x in 0..3 {
-            if self.get(x, 0) == LION.opponent() { return Result::Lose }
-        }
-        for i in 0..boards.len() {
-            boards[i] = boards[i].reverse().normalize()
+        if !win {
+            for x in 0..3 {
+                if self.get(x, 0) == LION.opponent() { return vec![] }
+            }
         }
-        Result::Unknown(boards)
+        moves
+    }
+
+    // make a move (the result is neither reversed nor normalized)
+    pub fn apply(&self, m: Move) -> Board {
+        let (nx, ny) = m.to;
+        let b = match m.from {
+            Some((x, y)) => self.del(x, y),
+            None => self.dec_hand(m.piece),
+        };
+        let b = if m.capture == EMPTY { b } else { b.del(nx, ny) };
+        let b = if m.capture == EMPTY || m.capture == LION.opponent() { b } else { b.inc_hand(m.capture.opponent()) };
+        b.put(nx, ny, if m.promotion { HEN } else { m.piece })
     }
 
     pub fn prev(&self) -> Vec<Board> {
+        bitboard::prev(*self)
+    }
+
+    // the reference implementation of prev (see also bitboard.rs)
+    pub fn prev_naive(&self) -> Vec<Board> {
         fn move_backward(boards: &mut Vec<Board>, b: Board, x: i8, y: i8, nx: i8, ny: i8, p: Piece) {
             let nb = b.put(nx, ny, p);
             boards.push(nb);
-            for p in vec![ELEPHANT, GIRAFFE, CHICK] {
+            for p in [ELEPHANT, GIRAFFE, CHICK] {
                 if b.hand(p) > 0 {
                     boards.push(nb.put(x, y, p.opponent()).dec_hand(p));
                     if p == CHICK {
@@ -224,11 +408,14 @@ impl Board {
                 match p {
                     LION | ELEPHANT | GIRAFFE | CHICK | HEN => {
                         let b2 = b.del(x, y);
-                        for m in p.moves() {
+                        // a chick on the far rank must have been dropped
+                        // (a chick that moves there is promoted)
+                        let moves = if p == CHICK && y == 3 { MOVE_DUMMY } else { p.moves() };
+                        for m in moves {
                             let nx = x - m.0;
-                            if nx < 0 || 2 < nx { continue }
+                            if !(0..=2).contains(&nx) { continue }
                             let ny = y - m.1;
-                            if ny < 0 || 3 < ny { continue }
+                            if !(0..=3).contains(&ny) { continue }
                             if b.get(nx, ny) != EMPTY { continue }
                             move_backward(&mut boards, b2, x, y, nx, ny, p);
                         }
@@ -243,53 +430,56 @@ impl Board {
                 }
             }
         }
-        for i in 0..boards.len() {
-            boards[i] = boards[i].normalize()
+        for b in boards.iter_mut() {
+            *b = b.normalize()
         }
         return boards;
     }
 
+    // check if the depth of the board is 3 or less
     pub fn easy(&self) -> bool {
-        if let Result::Unknown(bs) = self.next() {
-            // check if "try" is possible
-            for ref b in &bs {
-                if b.next() == Result::Lose { return true }
-            }
+        self.status() != Status::Unknown || self.easy_next().is_some()
+    }
 
-            // shallow search
-            for ref b in &bs {
-                if let Result::Unknown(nbs) = b.next() {
-                    let mut win = true;
-                    for nb in nbs {
-                        match nb.next() {
-                            Result::Win => {}
-                            Result::Lose => {
-                                win = false;
-                                break
-                            }
-                            Result::Unknown(nnbs) => {
-                                let mut lose = false;
-                                for nnb in nnbs {
-                                    if nnb.next() == Result::Lose {
-                                        lose = true;
-                                        break
-                                    }
-                                }
-                                if !lose {
-                                    win = false;
-                                    break
-                                }
-                            }
-                        }
+    // the depth (1 or 3) and the index of the next board (as next returns)
+    // that easy finds, or None if the depth is not determined (or if the
+    // status is not Unknown)
+    pub fn easy_next(&self) -> Option<(i32, usize)> {
+        if self.status() != Status::Unknown { return None }
+
+        // check if "try" is possible
+        let mut i = 0;
+        let mut found = false;
+        self.each_next(|b| {
+            found = b.status() == Status::Lose;
+            if !found { i += 1 }
+            !found
+        });
+        if found { return Some((1, i)) }
+
+        // shallow search
+        i = 0;
+        self.each_next(|b| {
+            let mut win = true;
+            let s = b.each_next(|nb| {
+                match nb.status() {
+                    Status::Win => {}
+                    Status::Lose => win = false,
+                    Status::Unknown => {
+                        let mut lose = false;
+                        nb.each_next(|nnb| {
+                            lose = nnb.status() == Status::Lose;
+                            !lose
+                        });
+                        if !lose { win = false }
                     }
-                    if win { return true }
                 }
-            }
-
-            false
-        }
-        else {
-            true
-        }
+                win
+            });
+            found = s == Status::Unknown && win;
+            if !found { i += 1 }
+            !found
+        });
+        if found { Some((3, i)) } else { None }
     }
 }
@@ -1,31 +1,193 @@
 // a custom implementation fo u64 hash set and map
 //
 // based on https://github.com/attractivechaos/klib/blob/master/khash.h
+//
+// BoardMap is generic over its value type (i32 by default), so a map can hold
+// u8 depths or a struct without a parallel map.
+//
+// Both can be saved as a flat image (see write_to), which SetImage and
+// MapImage map into memory read-only, without rebuilding the table.
 
-use std::ops::{Index, IndexMut};
+use std::cmp;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::iter::{Enumerate, FromIterator};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Index, IndexMut, Range};
+use std::path::Path;
+use std::slice;
 use board::Board;
+use mmap::Mmap;
 
 const FNV_OFFSET_BASIS : u64 = 0xcbf29ce484222325;
 const FNV_PRIME : u64 = 0x100000001b3;
 
 #[inline]
+#[allow(clippy::identity_op)] // `>> 0` keeps the bytes aligned
 fn hash(key: u64) -> usize {
     let h = FNV_OFFSET_BASIS;
-    let h = FNV_PRIME.wrapping_mul(h) ^ (((key >>  0) & 0xff) as u64);
-    let h = FNV_PRIME.wrapping_mul(h) ^ (((key >>  8) & 0xff) as u64);
-    let h = FNV_PRIME.wrapping_mul(h) ^ (((key >> 16) & 0xff) as u64);
-    let h = FNV_PRIME.wrapping_mul(h) ^ (((key >> 24) & 0xff) as u64);
-    let h = FNV_PRIME.wrapping_mul(h) ^ (((key >> 32) & 0xff) as u64);
-    let h = FNV_PRIME.wrapping_mul(h) ^ (((key >> 40) & 0xff) as u64);
-    let h = FNV_PRIME.wrapping_mul(h) ^ (((key >> 48) & 0xff) as u64);
-    let h = FNV_PRIME.wrapping_mul(h) ^ (((key >> 56) & 0xff) as u64);
+    let h = FNV_PRIME.wrapping_mul(h) ^ ((key >>  0) & 0xff);
+    let h = FNV_PRIME.wrapping_mul(h) ^ ((key >>  8) & 0xff);
+    let h = FNV_PRIME.wrapping_mul(h) ^ ((key >> 16) & 0xff);
+    let h = FNV_PRIME.wrapping_mul(h) ^ ((key >> 24) & 0xff);
+    let h = FNV_PRIME.wrapping_mul(h) ^ ((key >> 32) & 0xff);
+    let h = FNV_PRIME.wrapping_mul(h) ^ ((key >> 40) & 0xff);
+    let h = FNV_PRIME.wrapping_mul(h) ^ ((key >> 48) & 0xff);
+    let h = FNV_PRIME.wrapping_mul(h) ^ ((key >> 56) & 0xff);
     return h as usize;
 }
 
+// the number of buckets needed to hold n boards without resizing
+fn min_buckets(n: usize) -> usize {
+    let mut buckets = 4;
+    while (((buckets as f64) * 0.77).round() as usize) < n { buckets <<= 1 }
+    buckets
+}
+
+// the flags of a bucket (1: empty, 2: deleted)
+#[inline]
+fn flag(flags: &[u64], i: usize) -> u64 {
+    return (flags[i / 32] >> (i % 32 * 2)) & 3u64;
+}
+
+#[inline]
+fn is_invalid(flags: &[u64], i: usize) -> bool {
+    return flag(flags, i) != 0;
+}
+
+// find the bucket of a key in a table of n buckets (shared by the tables and
+// their images)
+#[inline]
+fn probe<F, K>(n: usize, key: u64, flag: F, key_at: K) -> Option<usize>
+    where F: Fn(usize) -> u64, K: Fn(usize) -> u64 {
+    if n == 0 { return None }
+    let mask = n - 1;
+    let mut i = hash(key) & mask;
+    let last = i;
+    let mut step = 0;
+    while flag(i) & 1 == 0 && (flag(i) & 2 != 0 || key_at(i) != key) {
+        step += 1;
+        i = (i + step) & mask;
+        if i == last { return None; }
+    }
+    return if flag(i) != 0 { None } else { Some(i) };
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// a value of BoardMap that can be saved in an image (see write_to)
+pub trait RawValue: Default {
+    const SIZE: usize;
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()>;
+    fn read<R: Read>(r: &mut R) -> io::Result<Self>;
+    fn decode(buf: &[u8]) -> Self;
+}
+
+macro_rules! raw_value {
+    ($($t:ty),*) => ($(
+        impl RawValue for $t {
+            const SIZE: usize = mem::size_of::<$t>();
+            fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+                w.write_all(&self.to_le_bytes())
+            }
+            fn read<R: Read>(r: &mut R) -> io::Result<Self> {
+                let mut buf = [0; mem::size_of::<$t>()];
+                r.read_exact(&mut buf)?;
+                Ok(<$t>::from_le_bytes(buf))
+            }
+            fn decode(buf: &[u8]) -> Self {
+                let mut b = [0; mem::size_of::<$t>()];
+                b.copy_from_slice(buf);
+                <$t>::from_le_bytes(b)
+            }
+        }
+    )*)
+}
+
+raw_value!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+// An image is a header followed by the arrays of the table as is (little
+// endian):
+//
+//   magic   (8 bytes): IMAGE_MAGIC
+//   version (4 bytes): IMAGE_VERSION
+//   kind    (1 byte) : 0 (BoardSet) or 1 (BoardMap)
+//   value   (1 byte) : the size of a value (0 for BoardSet)
+//   reserved (2 bytes)
+//   size, n_occupied, upper_bound, buckets (8 bytes each)
+//   flags   (8 bytes * ceil(buckets / 32))
+//   keys    (8 bytes * buckets)
+//   values  (value * buckets)
+//
+// Loading an image restores the same layout, and thus the same order of
+// iteration.
+pub const IMAGE_MAGIC : &[u8; 8] = b"\x89dobutab";
+pub const IMAGE_VERSION : u32 = 1;
+const IMAGE_HEADER_SIZE : usize = 48;
+
+struct ImageHeader {
+    kind: u8,
+    val_size: usize,
+    size: usize,
+    n_occupied: usize,
+    upper_bound: usize,
+    buckets: usize,
+}
+
+impl ImageHeader {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut buf = [0; IMAGE_HEADER_SIZE];
+        buf[..8].copy_from_slice(IMAGE_MAGIC);
+        buf[8..12].copy_from_slice(&IMAGE_VERSION.to_le_bytes());
+        buf[12] = self.kind;
+        buf[13] = self.val_size as u8;
+        let ns = [self.size, self.n_occupied, self.upper_bound, self.buckets];
+        for (i, &n) in ns.iter().enumerate() {
+            buf[16 + i * 8..24 + i * 8].copy_from_slice(&(n as u64).to_le_bytes());
+        }
+        w.write_all(&buf)
+    }
+
+    // parse and check a header for a table of the given kind
+    fn parse(buf: &[u8], kind: u8, val_size: usize) -> io::Result<Self> {
+        if buf.len() < IMAGE_HEADER_SIZE || &buf[..8] != IMAGE_MAGIC { return Err(invalid("bad magic")) }
+        let n = |i: usize| u64::decode(&buf[i..i + 8]) as usize;
+        let version = u32::decode(&buf[8..12]);
+        if version != IMAGE_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported version {}", version)))
+        }
+        if buf[12] != kind || buf[13] as usize != val_size { return Err(invalid("wrong kind of table")) }
+        let h = ImageHeader { kind, val_size, size: n(16), n_occupied: n(24), upper_bound: n(32), buckets: n(40) };
+        if !(h.buckets == 0 || h.buckets.is_power_of_two()) || h.size > h.n_occupied || h.n_occupied > h.buckets ||
+           h.upper_bound > h.buckets || h.buckets > usize::MAX / 16 {
+            return Err(invalid("broken table"))
+        }
+        Ok(h)
+    }
+
+    fn flag_words(&self) -> usize {
+        (self.buckets + 31) / 32
+    }
+
+    fn image_size(&self) -> usize {
+        IMAGE_HEADER_SIZE + (self.flag_words() + self.buckets) * 8 + self.val_size * self.buckets
+    }
+}
+
+// $V: the value type (() for BoardSet)
 macro_rules! def {
-    () => (
+    ($V:ty) => (
         #[inline]
-        fn is_empty(&self, i: usize) -> bool {
+        fn is_empty_at(&self, i: usize) -> bool {
             return (self.flags[i / 32] >> (i % 32 * 2)) & 1u64 != 0;
         }
         #[inline]
@@ -34,7 +196,7 @@ macro_rules! def {
         }
         #[inline]
         fn is_invalid(&self, i: usize) -> bool {
-            return (self.flags[i / 32] >> (i % 32 * 2)) & 3u64 != 0;
+            return is_invalid(&self.flags, i);
         }
         #[inline]
         fn set_deleted(&mut self, i: usize) {
@@ -57,6 +219,10 @@ macro_rules! def {
             return self.size;
         }
 
+        pub fn is_empty(&self) -> bool {
+            return self.size == 0;
+        }
+
         pub fn clear(&mut self) {
             self.size = 0;
             self.n_occupied = 0;
@@ -65,33 +231,20 @@ macro_rules! def {
         }
 
         pub fn contains(&self, b: Board) -> bool {
-            return self.get(b) != self.keys.len();
+            return self.find(b).is_some();
         }
 
-        pub fn get(&self, Board(key): Board) -> usize {
-            if self.keys.len() > 0 {
-                let k = hash(key);
-                let mask = self.keys.len() - 1;
-                let mut i = k & mask;
-                let last = i;
-                let mut step = 0;
-                while !self.is_empty(i) && (self.is_deleted(i) || self.keys[i] != key) {
-                    step += 1;
-                    i = (i + step) & mask;
-                    if i == last { return self.keys.len(); }
-                }
-                return if self.is_invalid(i) { self.keys.len() } else { i };
-            }
-            else {
-                return 0;
-            }
+        // the bucket of a given board
+        #[inline]
+        fn find(&self, Board(key): Board) -> Option<usize> {
+            return probe(self.keys.len(), key, |i| flag(&self.flags, i), |i| self.keys[i]);
         }
 
-        fn kick_out(&mut self, new_self: &mut Self, old_n_buckets: usize, key: u64, val: i32, new_mask: usize) {
+        fn kick_out(&mut self, new_self: &mut Self, old_n_buckets: usize, key: u64, val: $V, new_mask: usize) {
             let k = hash(key);
             let mut i = k & new_mask;
             let mut step = 0;
-            while !new_self.is_empty(i) {
+            while !new_self.is_empty_at(i) {
                 step += 1;
                 i = (i + step) & new_mask;
             }
@@ -150,6 +303,33 @@ macro_rules! def {
             return 0;
         }
 
+        pub fn with_capacity(n: usize) -> Self {
+            let mut t = Self::new();
+            t.reserve(n);
+            t
+        }
+
+        // make room for additional boards, so that they can be inserted
+        // without resizing
+        pub fn reserve(&mut self, additional: usize) {
+            if self.n_occupied + additional > self.upper_bound {
+                let n = cmp::max(min_buckets(self.size + additional), self.keys.len());
+                self.resize(n);
+            }
+        }
+
+        // resize to the smallest table that holds the current boards (if it
+        // is smaller), and release the unused memory
+        pub fn shrink_to_fit(&mut self) {
+            let n = min_buckets(self.size + 1);
+            if n < self.keys.len() {
+                self.resize(n);
+                self.flags.shrink_to_fit();
+                self.keys.shrink_to_fit();
+                self.shrink_vals();
+            }
+        }
+
         pub fn insert(&mut self, Board(key): Board) -> usize {
             if self.n_occupied >= self.upper_bound {
                 let m = if self.keys.len() > (self.size << 1) { self.keys.len() - 1 } else { self.keys.len() + 1 };
@@ -158,31 +338,33 @@ macro_rules! def {
             let mask = self.keys.len() - 1;
             let mut x = self.keys.len();
             let mut i = hash(key) & mask;
-            if self.is_empty(i) {
+            if self.is_empty_at(i) {
                 x = i;
             }
             else {
                 let mut step = 0;
                 let mut site = self.keys.len();
                 let last = i;
-                while !self.is_empty(i) && (self.is_deleted(i) || self.keys[i] != key) {
+                while !self.is_empty_at(i) && (self.is_deleted(i) || self.keys[i] != key) {
                     if self.is_deleted(i) { site = i }
                     step += 1;
                     i = (i + step) & mask;
                     if i == last { x = site; break; }
                 }
                 if x == self.keys.len() {
-                    x = if self.is_empty(i) && site != self.keys.len() { site } else { i };
+                    x = if self.is_empty_at(i) && site != self.keys.len() { site } else { i };
                 }
             }
-            if self.is_empty(x) {
+            if self.is_empty_at(x) {
                 self.keys[x] = key;
+                self.set_val(x, <$V>::default());
                 self.reset_both(x);
                 self.size += 1;
                 self.n_occupied += 1;
             }
             else if self.is_deleted(x) {
                 self.keys[x] = key;
+                self.set_val(x, <$V>::default());
                 self.reset_both(x);
                 self.size += 1;
             }
@@ -190,12 +372,35 @@ macro_rules! def {
         }
 
         pub fn delete(&mut self, key: Board) {
-            let x = self.get(key);
-            if x != self.keys.len() && !self.is_invalid(x) {
+            if let Some(x) = self.find(key) {
                 self.set_deleted(x);
                 self.size -= 1;
             }
         }
+
+        // write the header, flags and keys of an image (see write_to)
+        fn write_table<W: Write>(&self, w: &mut W, kind: u8, val_size: usize) -> io::Result<()> {
+            ImageHeader {
+                kind, val_size, size: self.size, n_occupied: self.n_occupied,
+                upper_bound: self.upper_bound, buckets: self.keys.len(),
+            }.write(w)?;
+            for &f in &self.flags { w.write_all(&f.to_le_bytes())? }
+            for &k in &self.keys { w.write_all(&k.to_le_bytes())? }
+            Ok(())
+        }
+
+        fn read_table<R: Read>(r: &mut R, kind: u8, val_size: usize) -> io::Result<Self> {
+            let mut buf = [0; IMAGE_HEADER_SIZE];
+            r.read_exact(&mut buf)?;
+            let h = ImageHeader::parse(&buf, kind, val_size)?;
+            let mut t = Self::default();
+            t.size = h.size;
+            t.n_occupied = h.n_occupied;
+            t.upper_bound = h.upper_bound;
+            t.flags = (0..h.flag_words()).map(|_| read_u64(r)).collect::<io::Result<_>>()?;
+            t.keys = (0..h.buckets).map(|_| read_u64(r)).collect::<io::Result<_>>()?;
+            Ok(t)
+        }
     )
 }
 
@@ -210,59 +415,495 @@ pub struct BoardSet {
 
 impl BoardSet {
     #[inline]
-    fn get_val(&self, _: usize) -> i32 { 0 }
+    fn get_val(&mut self, _: usize) { }
     #[inline]
-    fn set_val(&mut self, _: usize, _: i32) { }
+    fn set_val(&mut self, _: usize, _: ()) { }
     #[inline]
     fn resize_vals(&mut self, _: usize) { }
+    fn shrink_vals(&mut self) { }
 
-    def!();
+    def!(());
 
-    #[inline]
-    pub fn each<F>(&self, mut f: F) where F: FnMut(Board) -> () {
+    // write the set as an image
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_table(w, 0, 0)
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        Self::read_table(r, 0, 0)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    // read an image into memory (see also SetImage)
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        self.iter_range(0..self.keys.len())
+    }
+
+    // the number of buckets (see iter_range)
+    pub fn buckets(&self) -> usize {
+        self.keys.len()
+    }
+
+    // iterate the boards in a range of buckets (to split the work among
+    // threads)
+    pub fn iter_range<'a>(&'a self, r: Range<usize>) -> Iter<'a> {
+        Iter { set: self, r }
+    }
+
+    pub fn retain<F>(&mut self, mut f: F) where F: FnMut(Board) -> bool {
         for i in 0..self.keys.len() {
-            if self.is_invalid(i) { continue }
-            f(Board(self.keys[i]));
+            if self.is_invalid(i) || f(Board(self.keys[i])) { continue }
+            self.set_deleted(i);
+            self.size -= 1;
         }
     }
+
+    // take all the boards out, leaving the set empty
+    pub fn drain(&mut self) -> IntoIter {
+        mem::take(self).into_iter()
+    }
+
+    // insert many boards at a time (resizing at most once)
+    pub fn insert_all(&mut self, bs: &[Board]) {
+        self.reserve(bs.len());
+        for &b in bs { self.insert(b); }
+    }
+
+    // the boards in self or other
+    pub fn union<'a>(&'a self, other: &'a BoardSet) -> impl Iterator<Item = Board> + 'a {
+        self.iter().chain(other.difference(self))
+    }
+
+    // the boards in both self and other
+    pub fn intersection<'a>(&'a self, other: &'a BoardSet) -> impl Iterator<Item = Board> + 'a {
+        let (small, large) = if self.len() <= other.len() { (self, other) } else { (other, self) };
+        small.iter().filter(move |&b| large.contains(b))
+    }
+
+    // the boards in self but not in other
+    pub fn difference<'a>(&'a self, other: &'a BoardSet) -> impl Iterator<Item = Board> + 'a {
+        self.iter().filter(move |&b| !other.contains(b))
+    }
+
+    pub fn is_subset(&self, other: &BoardSet) -> bool {
+        self.len() <= other.len() && self.iter().all(|b| other.contains(b))
+    }
+}
+
+pub struct Iter<'a> {
+    set: &'a BoardSet,
+    r: Range<usize>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Board;
+    #[inline]
+    fn next(&mut self) -> Option<Board> {
+        let set = self.set;
+        self.r.find(|&i| !set.is_invalid(i)).map(|i| Board(set.keys[i]))
+    }
+}
+
+pub struct IntoIter {
+    set: BoardSet,
+    r: Range<usize>,
+}
+
+impl Iterator for IntoIter {
+    type Item = Board;
+    fn next(&mut self) -> Option<Board> {
+        let set = &self.set;
+        self.r.find(|&i| !set.is_invalid(i)).map(|i| Board(set.keys[i]))
+    }
+}
+
+impl<'a> IntoIterator for &'a BoardSet {
+    type Item = Board;
+    type IntoIter = Iter<'a>;
+    fn into_iter(self) -> Iter<'a> { self.iter() }
+}
+
+impl IntoIterator for BoardSet {
+    type Item = Board;
+    type IntoIter = IntoIter;
+    fn into_iter(self) -> IntoIter {
+        let r = 0..self.keys.len();
+        IntoIter { set: self, r }
+    }
+}
+
+impl FromIterator<Board> for BoardSet {
+    fn from_iter<I: IntoIterator<Item = Board>>(iter: I) -> Self {
+        let mut set = BoardSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<Board> for BoardSet {
+    fn extend<I: IntoIterator<Item = Board>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for b in iter { self.insert(b); }
+    }
 }
 
 #[derive(Default)]
-pub struct BoardMap {
+pub struct BoardMap<V = i32> {
     size: usize,
     n_occupied: usize,
     upper_bound: usize,
     flags: Vec<u64>,
     keys: Vec<u64>,
-    vals: Vec<i32>
+    vals: Vec<V>
 }
 
-impl BoardMap {
+impl<V: Default> BoardMap<V> {
     #[inline]
-    fn get_val(&self, i: usize) -> i32 { self.vals[i] }
+    fn get_val(&mut self, i: usize) -> V { mem::take(&mut self.vals[i]) }
     #[inline]
-    fn set_val(&mut self, i: usize, v: i32) { self.vals[i] = v }
+    fn set_val(&mut self, i: usize, v: V) { self.vals[i] = v }
     #[inline]
-    fn resize_vals(&mut self, n: usize) { self.vals.resize(n, 0) }
+    fn resize_vals(&mut self, n: usize) { self.vals.resize_with(n, V::default) }
+    fn shrink_vals(&mut self) { self.vals.shrink_to_fit() }
 
-    def!();
+    def!(V);
 
-    pub fn put(&mut self, b: Board, val: i32) {
+    pub fn put(&mut self, b: Board, val: V) {
         let x = self.insert(b);
         self.vals[x] = val;
     }
+
+    pub fn get(&self, b: Board) -> Option<&V> {
+        self.find(b).map(|x| &self.vals[x])
+    }
+
+    pub fn get_mut(&mut self, b: Board) -> Option<&mut V> {
+        match self.find(b) {
+            Some(x) => Some(&mut self.vals[x]),
+            None => None,
+        }
+    }
+
+    pub fn entry<'a>(&'a mut self, b: Board) -> Entry<'a, V> {
+        Entry { map: self, board: b }
+    }
+
+    // delete a board, and return its value
+    pub fn remove(&mut self, b: Board) -> Option<V> {
+        let x = self.find(b)?;
+        self.set_deleted(x);
+        self.size -= 1;
+        Some(self.get_val(x))
+    }
+
+    pub fn iter<'a>(&'a self) -> MapIter<'a, V> {
+        MapIter { map: self, r: 0..self.keys.len() }
+    }
+
+    pub fn iter_mut<'a>(&'a mut self) -> MapIterMut<'a, V> {
+        MapIterMut { flags: &self.flags, keys: &self.keys, vals: self.vals.iter_mut().enumerate() }
+    }
+
+    pub fn keys<'a>(&'a self) -> impl Iterator<Item = Board> + 'a {
+        self.iter().map(|(b, _)| b)
+    }
+
+    pub fn values<'a>(&'a self) -> impl Iterator<Item = &'a V> + 'a {
+        self.iter().map(|(_, v)| v)
+    }
+
+    pub fn retain<F>(&mut self, mut f: F) where F: FnMut(Board, &mut V) -> bool {
+        for i in 0..self.keys.len() {
+            if self.is_invalid(i) || f(Board(self.keys[i]), &mut self.vals[i]) { continue }
+            self.set_deleted(i);
+            self.size -= 1;
+            self.vals[i] = V::default();
+        }
+    }
+
+    // take all the boards and values out, leaving the map empty
+    pub fn drain(&mut self) -> MapIntoIter<V> {
+        mem::take(self).into_iter()
+    }
+}
+
+pub struct MapIter<'a, V: 'a> {
+    map: &'a BoardMap<V>,
+    r: Range<usize>,
+}
+
+impl<'a, V: Default> Iterator for MapIter<'a, V> {
+    type Item = (Board, &'a V);
+    #[inline]
+    fn next(&mut self) -> Option<(Board, &'a V)> {
+        let map = self.map;
+        self.r.find(|&i| !map.is_invalid(i)).map(|i| (Board(map.keys[i]), &map.vals[i]))
+    }
+}
+
+pub struct MapIterMut<'a, V: 'a> {
+    flags: &'a [u64],
+    keys: &'a [u64],
+    vals: Enumerate<slice::IterMut<'a, V>>,
+}
+
+impl<'a, V> Iterator for MapIterMut<'a, V> {
+    type Item = (Board, &'a mut V);
+    #[inline]
+    fn next(&mut self) -> Option<(Board, &'a mut V)> {
+        let (flags, keys) = (self.flags, self.keys);
+        self.vals.find(|&(i, _)| !is_invalid(flags, i)).map(|(i, v)| (Board(keys[i]), v))
+    }
+}
+
+pub struct MapIntoIter<V> {
+    map: BoardMap<V>,
+    r: Range<usize>,
+}
+
+impl<V: Default> Iterator for MapIntoIter<V> {
+    type Item = (Board, V);
+    fn next(&mut self) -> Option<(Board, V)> {
+        let i = {
+            let map = &self.map;
+            self.r.find(|&i| !map.is_invalid(i))?
+        };
+        Some((Board(self.map.keys[i]), self.map.get_val(i)))
+    }
+}
+
+impl<'a, V: Default> IntoIterator for &'a BoardMap<V> {
+    type Item = (Board, &'a V);
+    type IntoIter = MapIter<'a, V>;
+    fn into_iter(self) -> MapIter<'a, V> { self.iter() }
+}
+
+impl<'a, V: Default> IntoIterator for &'a mut BoardMap<V> {
+    type Item = (Board, &'a mut V);
+    type IntoIter = MapIterMut<'a, V>;
+    fn into_iter(self) -> MapIterMut<'a, V> { self.iter_mut() }
+}
+
+impl<V: Default> IntoIterator for BoardMap<V> {
+    type Item = (Board, V);
+    type IntoIter = MapIntoIter<V>;
+    fn into_iter(self) -> MapIntoIter<V> {
+        let r = 0..self.keys.len();
+        MapIntoIter { map: self, r }
+    }
+}
+
+impl<V: Default> FromIterator<(Board, V)> for BoardMap<V> {
+    fn from_iter<I: IntoIterator<Item = (Board, V)>>(iter: I) -> Self {
+        let mut map = BoardMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<V: Default> Extend<(Board, V)> for BoardMap<V> {
+    fn extend<I: IntoIterator<Item = (Board, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for (b, v) in iter { self.put(b, v) }
+    }
+}
+
+// a board in a map, which may be absent (see BoardMap::entry)
+pub struct Entry<'a, V: 'a> {
+    map: &'a mut BoardMap<V>,
+    board: Board,
+}
+
+impl<'a, V: Default> Entry<'a, V> {
+    pub fn or_insert(self, val: V) -> &'a mut V {
+        self.or_insert_with(|| val)
+    }
+
+    pub fn or_insert_with<F>(self, f: F) -> &'a mut V where F: FnOnce() -> V {
+        let x = match self.map.find(self.board) {
+            Some(x) => x,
+            None => {
+                let x = self.map.insert(self.board);
+                self.map.vals[x] = f();
+                x
+            }
+        };
+        &mut self.map.vals[x]
+    }
+
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn and_modify<F>(self, f: F) -> Self where F: FnOnce(&mut V) {
+        if let Some(v) = self.map.get_mut(self.board) { f(v) }
+        self
+    }
+}
+
+impl<V: RawValue> BoardMap<V> {
+    // write the map as an image (see BoardSet::write_to)
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_table(w, 1, V::SIZE)?;
+        for v in &self.vals { v.write(w)? }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut t = Self::read_table(r, 1, V::SIZE)?;
+        t.vals = (0..t.keys.len()).map(|_| V::read(r)).collect::<io::Result<_>>()?;
+        Ok(t)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    // read an image into memory (see also MapImage)
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
 }
 
-impl Index<Board> for BoardMap {
-    type Output = i32;
-    fn index<'a>(&'a self, b: Board) -> &'a i32 {
-        &self.vals[self.get(b)]
+// panics if the board is absent (use get instead)
+impl<V: Default> Index<Board> for BoardMap<V> {
+    type Output = V;
+    fn index(&self, b: Board) -> &V {
+        match self.get(b) {
+            Some(v) => v,
+            None => panic!("no such board: {:015x}", b.0),
+        }
     }
 }
 
-impl IndexMut<Board> for BoardMap {
-    fn index_mut(&mut self, b: Board) -> &mut i32 {
+// inserts the board (with the default value) if absent
+impl<V: Default> IndexMut<Board> for BoardMap<V> {
+    fn index_mut(&mut self, b: Board) -> &mut V {
         let x = self.insert(b);
         &mut self.vals[x]
     }
 }
+
+// check if a file is an image (written by save)
+pub fn is_image<P: AsRef<Path>>(path: P) -> bool {
+    let mut magic = [0; 8];
+    match File::open(path) {
+        Ok(mut f) => f.read_exact(&mut magic).is_ok() && &magic == IMAGE_MAGIC,
+        Err(_) => false,
+    }
+}
+
+// an image of a table mapped into memory read-only
+struct Image {
+    data: Mmap,
+    h: ImageHeader,
+    keys: usize, // the offset of keys
+    vals: usize, // the offset of values
+}
+
+impl Image {
+    fn open<P: AsRef<Path>>(path: P, kind: u8, val_size: usize) -> io::Result<Image> {
+        let data = Mmap::open(path)?;
+        let h = ImageHeader::parse(&data, kind, val_size)?;
+        if data.len() != h.image_size() { return Err(invalid("truncated image")) }
+        let keys = IMAGE_HEADER_SIZE + h.flag_words() * 8;
+        let vals = keys + h.buckets * 8;
+        Ok(Image { data, h, keys, vals })
+    }
+
+    #[inline]
+    fn u64_at(&self, offset: usize) -> u64 {
+        u64::decode(&self.data[offset..offset + 8])
+    }
+
+    #[inline]
+    fn flag(&self, i: usize) -> u64 {
+        (self.u64_at(IMAGE_HEADER_SIZE + i / 32 * 8) >> (i % 32 * 2)) & 3
+    }
+
+    #[inline]
+    fn key(&self, i: usize) -> u64 {
+        self.u64_at(self.keys + i * 8)
+    }
+
+    #[inline]
+    fn find(&self, Board(key): Board) -> Option<usize> {
+        probe(self.h.buckets, key, |i| self.flag(i), |i| self.key(i))
+    }
+
+    fn val(&self, i: usize) -> &[u8] {
+        let offset = self.vals + i * self.h.val_size;
+        &self.data[offset..offset + self.h.val_size]
+    }
+
+    fn buckets<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
+        (0..self.h.buckets).filter(move |&i| self.flag(i) == 0)
+    }
+}
+
+// a BoardSet image (written by BoardSet::save) mapped read-only
+pub struct SetImage(Image);
+
+impl SetImage {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(SetImage(Image::open(path, 0, 0)?))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.h.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, b: Board) -> bool {
+        self.0.find(b).is_some()
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = Board> + 'a {
+        self.0.buckets().map(move |i| Board(self.0.key(i)))
+    }
+}
+
+// a BoardMap image (written by BoardMap::save) mapped read-only
+pub struct MapImage<V>(Image, PhantomData<V>);
+
+impl<V: RawValue> MapImage<V> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(MapImage(Image::open(path, 1, V::SIZE)?, PhantomData))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.h.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, b: Board) -> bool {
+        self.0.find(b).is_some()
+    }
+
+    pub fn get(&self, b: Board) -> Option<V> {
+        self.0.find(b).map(|i| V::decode(self.0.val(i)))
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (Board, V)> + 'a {
+        self.0.buckets().map(move |i| (Board(self.0.key(i)), V::decode(self.0.val(i))))
+    }
+}
@@ -0,0 +1,179 @@
+// Compress and encode the data base (see phf.rs) as a text, by range coder.
+//
+// The text is a base-93 stream that consists of ASCII code 32, 33, 35-91,
+// 93-126 (i.e., printable characters except '"' and '\'):
+//
+//   header: the three seed primes, the frequency of 0, the number of symbols,
+//           and the table size (3 digits each)
+//   frequency table: symbol + frequency * 34 * 37 * 3 (4 digits each, in
+//                    order of symbols, except 0)
+//   data: the range-coded table
+//
+// encode is a port of 6-compress.rb, and decode is the same as the
+// constructor of AI in client/src/ai.ts.
+
+use std::collections::BTreeMap;
+use phf::Phf;
+
+const BASE : u64 = 93;
+const MAX_RANGE : u64 = BASE * BASE * BASE * BASE * BASE;
+const MIN_RANGE : u64 = BASE * BASE * BASE * BASE;
+
+// the upper bound of the symbols (value * 3 + assignment)
+const SYMBOLS : u64 = 34 * 37 * 3;
+
+// n => n-digit base-93 number
+fn dump(r: u64, n: u32, out: &mut Vec<u64>) {
+    for i in (0..n).rev() {
+        out.push((r / BASE.pow(i)) % BASE);
+    }
+}
+
+pub fn encode(phf: &Phf) -> String {
+    let a = &phf.table;
+
+    // count the frequency and its accumulation
+    let mut count = BTreeMap::new();
+    for &c in a { *count.entry(c as u64).or_insert(0) += 1 }
+    // the header assumes that 0 (an empty slot) is the first symbol
+    assert!(count.contains_key(&0));
+    let mut count_sum = BTreeMap::new();
+    let mut sum = 0;
+    for (&c, &n) in &count {
+        count_sum.insert(c, sum);
+        sum += n;
+    }
+
+    // range coder compression
+    let mut low = 0;
+    let mut range = MAX_RANGE;
+    let mut cnt = 0;
+    let mut buff = 0;
+    let mut aa = vec![];
+    for &c in a {
+        let c = c as u64;
+        let tmp = range / sum;
+        low += count_sum[&c] * tmp;
+        range = count[&c] * tmp;
+        if low >= MAX_RANGE {
+            buff += 1;
+            low %= MAX_RANGE;
+            if cnt > 0 {
+                aa.push(buff);
+                for _ in 0..cnt - 1 { aa.push(0) }
+                buff = 0;
+                cnt = 0;
+            }
+        }
+        while range < MIN_RANGE {
+            if low < (BASE - 1) * MIN_RANGE {
+                aa.push(buff);
+                for _ in 0..cnt { aa.push(BASE - 1) }
+                buff = (low / MIN_RANGE) % BASE;
+                cnt = 0;
+            }
+            else {
+                cnt += 1;
+            }
+            low = (low * BASE) % MAX_RANGE;
+            range *= BASE;
+        }
+    }
+    let mut c = BASE - 1;
+    if low >= MAX_RANGE {
+        buff += 1;
+        c = 0;
+    }
+    aa.push(buff);
+    for _ in 0..cnt { aa.push(c) }
+    dump(low, 5, &mut aa);
+    aa.remove(0); // drop the head because it is always zero
+
+    // header
+    let header = [phf.ps[0], phf.ps[1], phf.ps[2], count[&0], count.len() as u64, a.len() as u64];
+    let mut out = vec![];
+    for &n in header.iter() {
+        assert!(n < BASE.pow(3));
+        dump(n, 3, &mut out);
+    }
+    for (&ch, &n) in &count {
+        if ch == 0 { continue }
+        let n = ch + n * SYMBOLS;
+        assert!(n < BASE.pow(4));
+        dump(n, 4, &mut out);
+    }
+
+    out.iter().chain(aa.iter()).map(|&n| {
+        let n = n + 32;
+        let n = if n >= 34 { n + 1 } else { n };
+        let n = if n >= 92 { n + 1 } else { n };
+        n as u8 as char
+    }).collect()
+}
+
+pub fn decode(buf: &str) -> Option<Phf> {
+    let buf = buf.as_bytes();
+    let mut offset = 0;
+    let mut fetch = |n: usize| -> Option<u64> {
+        let mut r = 0;
+        for _ in 0..n {
+            let c = (*buf.get(offset)? as u64).checked_sub(32)?;
+            offset += 1;
+            r = r * BASE + if c < 2 { c } else if c < 60 { c - 1 } else { c - 2 };
+        }
+        Some(r)
+    };
+
+    // Extract the seed primes
+    let ps = [fetch(3)?, fetch(3)?, fetch(3)?];
+
+    // Extract the frequency table for range coder
+    let mut sum = fetch(3)?;
+    let mut freq = vec![0; SYMBOLS as usize];
+    freq[0] = sum;
+    let mut freq_sym = vec![0];
+    let mut freq_accum = vec![0];
+    let freq_size = fetch(3)?;
+    let data_size = fetch(3)?;
+    for _ in 0..freq_size.checked_sub(1)? {
+        let n = fetch(4)?;
+        let c = n / SYMBOLS;
+        let ch = n % SYMBOLS;
+        freq[ch as usize] = c;
+        freq_sym.push(ch);
+        freq_accum.push(sum);
+        sum += c;
+    }
+    freq_accum.push(sum);
+    if sum == 0 { return None }
+
+    // Decompress the data base
+    let mut low = fetch(5)?;
+    let mut range = MAX_RANGE;
+    let mut table = vec![];
+    for _ in 0..data_size {
+        let v = range / sum;
+        if v == 0 { return None }
+        let (mut l, mut h) = (0, freq_accum.len() - 1);
+        while l < h {
+            let k = (l + h) / 2;
+            if freq_accum[k + 1] <= low / v {
+                l = k + 1;
+            }
+            else {
+                h = k;
+            }
+        }
+        let c = *freq_sym.get(l)?;
+        low = low.checked_sub(v * freq_accum[l])?;
+        range = v * freq[c as usize];
+        while range < MIN_RANGE {
+            if range == 0 { return None }
+            range *= BASE;
+            low = ((low * BASE) + fetch(1)?) % MAX_RANGE;
+        }
+        table.push(c as u32);
+    }
+
+    Some(Phf { ps, table })
+}
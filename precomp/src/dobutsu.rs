@@ -0,0 +1,421 @@
+// A single command for the whole pipeline and the data bases.
+//
+// Usage: dobutsu command [options] [args]
+//
+//   enum     [-o 1.txt]               enumerate all reachable boards (1-enum)
+//   analyze  [-i 1.txt] [-o 2.txt]    retrospective analysis (2-analyze)
+//   extract  [-i 2.txt] [-o 3.txt]    extract the needed boards (3-extract)
+//   verify   [-i 4.txt] [-o 9.txt]    check the final boards (9-test)
+//   image    [-i 2.txt] [-o 2.img]    save the tablebase as an image
+//   probe    [-i 2.txt] position...   show the value and the best moves
+//   show     position...              show boards
+//   play     [-i ai.txt]              play black against the oracle
+//   pipeline [-d dir]                 run all stages that are not up to date
+//
+//   -i, -o: input and output paths ("-" for stdin and stdout)
+//   -d: the directory of the intermediate files (default: .)
+//   --format text|binary: the format of the intermediate files (see lib.rs)
+//   --check: validate the input boards (see Board::validate)
+//   --threads N, --counter, --checkpoint dir, --resume: passed to 2-analyze
+//   --quiet: suppress the progress logs (unless a stage fails)
+//
+//   position: SFEN-like notation (see sfen.rs) or hex representation of
+//             bit-board (black to move)
+//
+// The stages are run as the sibling binaries of this command (e.g.,
+// target/release/2-analyze).  Each output is written to a temporary file and
+// renamed when the stage succeeds, so a broken output is never left.
+//
+// An image of the tablebase (see board_collection.rs) is mapped into memory
+// instead of being parsed, so `probe -i 2.img` starts instantly.  `extract`
+// also accepts it as its input.
+//
+// `pipeline` works like the Makefile: a stage is skipped if all its outputs
+// are newer than its inputs (and the stage binary).  4.sol must be prepared
+// by an LP solver (or `make 4.sol`).
+
+#[macro_use]
+extern crate precomp;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::time::SystemTime;
+use precomp::board::{Board, Color};
+use precomp::board_collection;
+use precomp::kifu::{self, Style};
+use precomp::oracle::Oracle;
+use precomp::position::{Outcome, Position, RepetitionRule};
+use precomp::sfen;
+use precomp::tablebase::{Tablebase, Value};
+
+fn usage() -> ! {
+    log!("usage: dobutsu (enum|analyze|extract|verify|image|probe|show|play|pipeline) [options] [args]");
+    log!("  options: -i path, -o path, -d dir, --format text|binary, --threads N, --counter,");
+    log!("           --checkpoint dir, --resume, --check, --quiet");
+    process::exit(1)
+}
+
+#[derive(Default)]
+struct Opts {
+    input: Option<String>,
+    output: Option<String>,
+    dir: Option<PathBuf>,
+    format: Option<String>,
+    threads: Option<String>,
+    counter: bool,
+    checkpoint: Option<String>,
+    resume: bool,
+    check: bool,
+    quiet: bool,
+    args: Vec<String>,
+}
+
+impl Opts {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Opts {
+        let mut opts = Opts::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-i" | "--input" => opts.input = Some(args.next().unwrap_or_else(|| usage())),
+                "-o" | "--output" => opts.output = Some(args.next().unwrap_or_else(|| usage())),
+                "-d" | "--dir" => opts.dir = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+                "--format" => opts.format = Some(args.next().unwrap_or_else(|| usage())),
+                "--threads" => opts.threads = Some(args.next().unwrap_or_else(|| usage())),
+                "--check" => opts.check = true,
+                "--counter" => opts.counter = true,
+                "--checkpoint" => opts.checkpoint = Some(args.next().unwrap_or_else(|| usage())),
+                "--resume" => opts.resume = true,
+                "-q" | "--quiet" => opts.quiet = true,
+                _ if arg.starts_with('-') && arg != "-" => {
+                    log!("unknown option: {}", arg);
+                    usage()
+                }
+                _ => opts.args.push(arg),
+            }
+        }
+        opts
+    }
+
+    fn input<'a>(&'a self, default: &'a str) -> &'a str {
+        self.input.as_ref().map_or(default, |s| s.as_str())
+    }
+
+    fn output<'a>(&'a self, default: &'a str) -> &'a str {
+        self.output.as_ref().map_or(default, |s| s.as_str())
+    }
+
+    // the options passed to a stage binary
+    fn stage_args(&self, format: bool, check: bool) -> Vec<String> {
+        let mut args = vec![];
+        if let (true, Some(f)) = (format, self.format.as_ref()) {
+            args.push("--format".to_string());
+            args.push(f.clone());
+        }
+        if check && self.check { args.push("--check".to_string()) }
+        args
+    }
+
+    fn analyze_args(&self) -> Vec<String> {
+        let mut args = self.stage_args(true, true);
+        if let Some(ref n) = self.threads {
+            args.push("--threads".to_string());
+            args.push(n.clone());
+        }
+        if self.counter { args.push("--counter".to_string()) }
+        if let Some(ref dir) = self.checkpoint {
+            args.push("--checkpoint".to_string());
+            args.push(dir.clone());
+        }
+        if self.resume { args.push("--resume".to_string()) }
+        args
+    }
+}
+
+// an invocation of a stage binary
+struct Stage {
+    bin: &'static str,
+    args: Vec<String>,
+    stdin: Option<PathBuf>,  // None: inherit
+    stdout: Option<PathBuf>, // None: inherit
+    files: Vec<(PathBuf, PathBuf)>, // outputs written by the stage itself (tmp, path)
+}
+
+impl Stage {
+    fn new(bin: &'static str, args: Vec<String>) -> Stage {
+        Stage { bin, args, stdin: None, stdout: None, files: vec![] }
+    }
+
+    // "-" means stdin
+    fn stdin(mut self, path: &str) -> Stage {
+        if path != "-" { self.stdin = Some(PathBuf::from(path)) }
+        self
+    }
+
+    // "-" means stdout
+    fn stdout(mut self, path: &str) -> Stage {
+        if path != "-" { self.stdout = Some(PathBuf::from(path)) }
+        self
+    }
+
+    // a file that the stage writes by itself at tmp (renamed to path)
+    fn file(mut self, tmp: PathBuf, path: PathBuf) -> Stage {
+        self.files.push((tmp, path));
+        self
+    }
+
+    fn run(&self, quiet: bool) -> Result<(), String> {
+        let bin = bin_path(self.bin)?;
+        let mut cmd = Command::new(&bin);
+        cmd.args(&self.args);
+        if let Some(ref path) = self.stdin {
+            cmd.stdin(File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?);
+        }
+        let mut files = self.files.clone();
+        if let Some(ref path) = self.stdout {
+            let tmp = tmp_path(path);
+            cmd.stdout(File::create(&tmp).map_err(|e| format!("{}: {}", tmp.display(), e))?);
+            files.push((tmp, path.clone()));
+        }
+        // with --quiet, the logs are kept and shown only if the stage fails
+        if quiet { cmd.stderr(Stdio::piped()); }
+
+        let r = match cmd.spawn().and_then(|child| child.wait_with_output()) {
+            Ok(ref out) if out.status.success() => Ok(()),
+            Ok(out) => {
+                let _ = io::stderr().write_all(&out.stderr);
+                Err(format!("{} failed ({})", self.bin, out.status))
+            }
+            Err(e) => Err(format!("{}: {}", bin.display(), e)),
+        };
+        for (tmp, path) in files {
+            if r.is_ok() {
+                fs::rename(&tmp, &path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+            else {
+                let _ = fs::remove_file(&tmp);
+            }
+        }
+        r
+    }
+}
+
+// the path of a stage binary (in the same directory as this command)
+fn bin_path(name: &str) -> Result<PathBuf, String> {
+    let exe = env::current_exe().map_err(|e| e.to_string())?;
+    let path = exe.with_file_name(format!("{}{}", name, env::consts::EXE_SUFFIX));
+    if !path.exists() { return Err(format!("{} not found (run `cargo build --release`)", path.display())) }
+    Ok(path)
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// check if all outputs exist and are newer than all inputs
+fn up_to_date(outputs: &[PathBuf], inputs: &[PathBuf]) -> bool {
+    let oldest = match outputs.iter().map(|p| mtime(p)).collect::<Option<Vec<_>>>() {
+        Some(ts) => ts.into_iter().min(),
+        None => return false,
+    };
+    inputs.iter().all(|p| match (mtime(p), oldest) {
+        (Some(t), Some(oldest)) => t <= oldest,
+        _ => false,
+    })
+}
+
+// parse a position given in the command line
+fn position(arg: &str) -> Result<Position, String> {
+    match sfen::parse(arg) {
+        Ok((b, side)) => Ok(Position::new(b, side)),
+        Err(e) => match arg.parse::<Board>() {
+            Ok(b) => Ok(Position::new(b, Color::Black)),
+            Err(_) => Err(e.to_string()),
+        }
+    }
+}
+
+fn describe(v: Value) -> String {
+    match v {
+        Value::Win(n) => format!("win in {} plies", n),
+        Value::Loss(n) => format!("loss in {} plies", n),
+        Value::Draw => "draw".to_string(),
+    }
+}
+
+fn show(pos: &Position) {
+    println!("{}", pos);
+    println!("board: {} (canonical: {})", pos.board(), pos.canonical());
+    pos.absolute().show();
+}
+
+fn probe(opts: &Opts) -> Result<(), String> {
+    if opts.args.is_empty() { usage() }
+    let path = opts.input("2.txt");
+    if !opts.quiet { log!("loading {}...", path) }
+    let tb = Tablebase::load(path).map_err(|e| format!("{}: {}", path, e))?;
+    for arg in &opts.args {
+        let pos = position(arg)?;
+        let b = pos.board();
+        println!("{}", pos);
+        match tb.probe(b) {
+            Some(v) => println!("  value: {}", describe(v)),
+            None => { println!("  value: unknown (unreachable board)"); continue }
+        }
+        let best = tb.best_moves(b);
+        for m in b.legal_moves() {
+            let v = tb.probe_move(b, m).map_or("unknown".to_string(), describe);
+            let mark = if best.contains(&m) { "*" } else { " " };
+            println!("  {} {} {}", mark, kifu::write_move(b, pos.side(), m, Style::Japanese), v);
+        }
+    }
+    Ok(())
+}
+
+fn image(opts: &Opts) -> Result<(), String> {
+    let (input, output) = (opts.input("2.txt"), Path::new(opts.output("2.img")));
+    if !opts.quiet { log!("loading {}...", input) }
+    let tb = Tablebase::load(input).map_err(|e| format!("{}: {}", input, e))?;
+    let tmp = tmp_path(output);
+    tb.save(&tmp).map_err(|e| format!("{}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, output).map_err(|e| format!("{}: {}", output.display(), e))?;
+    if !opts.quiet { log!("{}: {} boards", output.display(), tb.len()) }
+    Ok(())
+}
+
+fn play(opts: &Opts) -> Result<(), String> {
+    let path = opts.input("ai.txt");
+    let oracle = Oracle::load(path).map_err(|e| format!("{}: {}", path, e))?;
+    let rule = RepetitionRule::default();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut pos = Position::init();
+    println!("You are black (the first player).  Enter moves like \"B3B2\" or \"▲B2ひよこ\".");
+    loop {
+        show(&pos);
+        if let Some(outcome) = pos.outcome(rule) {
+            match outcome {
+                Outcome::Win(Color::Black) => println!("You win!"),
+                Outcome::Win(Color::White) => println!("You lose."),
+                Outcome::Draw => println!("Draw."),
+            }
+            return Ok(())
+        }
+        let b = pos.board();
+        if pos.side() == Color::Black {
+            print!("your move> ");
+            io::stdout().flush().map_err(|e| e.to_string())?;
+            let line = match lines.next() {
+                Some(line) => line.map_err(|e| e.to_string())?,
+                None => return Ok(()),
+            };
+            match kifu::read_move(b, Color::Black, line.trim()) {
+                Some(m) => pos.make(m),
+                None => println!("illegal move: {}", line.trim()),
+            }
+        }
+        else {
+            let (depth, nb) = oracle.next(b).ok_or("the oracle gave up")?;
+            let m = pos.legal_moves().into_iter().find(|&m| b.apply(m) == nb).ok_or("the oracle made an illegal move")?;
+            println!("white: {} (white wins in {} plies)", kifu::write_move(b, Color::White, m, Style::Japanese), depth);
+            pos.make(m);
+        }
+    }
+}
+
+// run all stages (like the Makefile)
+fn pipeline(opts: &Opts) -> Result<(), String> {
+    let dir = opts.dir.clone().unwrap_or_else(|| PathBuf::from("."));
+    let f = |name: &str| dir.join(name);
+    let s = |name: &str| f(name).to_string_lossy().into_owned();
+
+    let mut sol_args = opts.stage_args(true, false);
+    sol_args.extend(vec![s("3.txt"), s("4.sol"), s("4.lp.map")]);
+
+    // (stage, inputs, outputs)
+    let stages = vec![
+        (Stage::new("1-enum", opts.stage_args(true, false)).stdout(&s("1.txt")),
+         vec![], vec![f("1.txt")]),
+        (Stage::new("2-analyze", opts.analyze_args()).stdin(&s("1.txt")).stdout(&s("2.txt")),
+         vec![f("1.txt")], vec![f("2.txt")]),
+        (Stage::new("3-extract", opts.stage_args(true, true)).stdin(&s("2.txt")).stdout(&s("3.txt")),
+         vec![f("2.txt")], vec![f("3.txt")]),
+        // 4-opt writes 4.lp and 4.lp.map by itself (a path that ends with
+        // .lp, and the same path with .map)
+        (Stage::new("4-opt", vec![s("3.txt"), s("4.tmp.lp")])
+            .file(f("4.tmp.lp"), f("4.lp")).file(f("4.tmp.lp.map"), f("4.lp.map")),
+         vec![f("3.txt")], vec![f("4.lp"), f("4.lp.map")]),
+        (Stage::new("4-opt", sol_args).stdout(&s("4.txt")),
+         vec![f("3.txt"), f("4.sol"), f("4.lp.map")], vec![f("4.txt")]),
+        (Stage::new("5-phf", opts.stage_args(true, true)).stdin(&s("4.txt")).stdout(&s("5.txt")),
+         vec![f("4.txt")], vec![f("5.txt")]),
+        (Stage::new("6-compress", vec![]).stdin(&s("5.txt")).stdout(&s("ai.txt")),
+         vec![f("5.txt")], vec![f("ai.txt")]),
+        (Stage::new("9-test", opts.stage_args(true, true)).stdin(&s("4.txt")).stdout(&s("9.txt")),
+         vec![f("4.txt")], vec![f("9.txt")]),
+    ];
+
+    for (stage, mut inputs, outputs) in stages {
+        // 4.sol is not made by this pipeline
+        if stage.args.iter().any(|a| a.ends_with("4.sol")) && !up_to_date(&[f("4.sol")], &[f("4.lp")]) {
+            return Err(format!("{} is missing or older than 4.lp (solve 4.lp by an LP solver, or run `make 4.sol`)",
+                f("4.sol").display()))
+        }
+        inputs.push(bin_path(stage.bin)?);
+        let names: Vec<String> = outputs.iter().map(|p| p.display().to_string()).collect();
+        if up_to_date(&outputs, &inputs) {
+            if !opts.quiet { log!("{}: up to date", names.join(", ")) }
+            continue
+        }
+        if !opts.quiet { log!("{}: running {}", names.join(", "), stage.bin) }
+        stage.run(opts.quiet)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let cmd = args.next().unwrap_or_else(|| usage());
+    let opts = Opts::parse(args);
+
+    let r = match cmd.as_str() {
+        "enum" =>
+            Stage::new("1-enum", opts.stage_args(true, false))
+                .stdout(opts.output("1.txt")).run(opts.quiet),
+        "analyze" =>
+            Stage::new("2-analyze", opts.analyze_args())
+                .stdin(opts.input("1.txt")).stdout(opts.output("2.txt")).run(opts.quiet),
+        "extract" if board_collection::is_image(opts.input("2.txt")) => {
+            let mut args = opts.stage_args(true, false);
+            args.push("--image".to_string());
+            args.push(opts.input("2.txt").to_string());
+            Stage::new("3-extract", args).stdout(opts.output("3.txt")).run(opts.quiet)
+        }
+        "extract" =>
+            Stage::new("3-extract", opts.stage_args(true, true))
+                .stdin(opts.input("2.txt")).stdout(opts.output("3.txt")).run(opts.quiet),
+        "verify" =>
+            Stage::new("9-test", opts.stage_args(true, true))
+                .stdin(opts.input("4.txt")).stdout(opts.output("9.txt")).run(opts.quiet),
+        "image" => image(&opts),
+        "probe" => probe(&opts),
+        "show" => {
+            if opts.args.is_empty() { usage() }
+            opts.args.iter().try_for_each(|arg| position(arg).map(|pos| show(&pos)))
+        }
+        "play" => play(&opts),
+        "pipeline" => pipeline(&opts),
+        _ => usage(),
+    };
+    if let Err(msg) = r {
+        log!("{}", msg);
+        process::exit(1)
+    }
+}
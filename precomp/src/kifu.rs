@@ -0,0 +1,223 @@
+// a game record (kifu) reader and writer
+//
+// A record is a list of moves from the initial board, one move per line (or
+// separated by spaces).  Each move is written in one of the two styles:
+//
+//   Japanese: "▲B2ひよこ", "△C3にわとり上", "▲B1ひよこ成", "▲C3ひよこ打"
+//             (the same notation as the client UI)
+//   ASCII:    "B3B2", "B2B1+", "C*B3"
+//
+// The cells are written in the absolute orientation: files A-C from black's
+// left, and ranks 1-4 from white's side.  Lines starting with '#' are ignored.
+
+use std::fmt;
+use board::{Board, Color, Move, Piece, LION, ELEPHANT, GIRAFFE, CHICK, HEN};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Style { Japanese, Ascii }
+
+// ply: 1-origin index of the move where the error occurs
+#[derive(Debug, PartialEq, Eq)]
+pub struct KifuError {
+    pub ply: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for KifuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ply {}: {}", self.ply, self.msg)
+    }
+}
+
+// moves are seen from the player to move (i.e., as Board::legal_moves returns)
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Kifu {
+    pub moves: Vec<Move>,
+}
+
+fn name(p: Piece) -> &'static str {
+    match p {
+        LION     => "ライオン",
+        ELEPHANT => "ぞう",
+        GIRAFFE  => "きりん",
+        CHICK    => "ひよこ",
+        HEN      => "にわとり",
+        _ => unreachable!(),
+    }
+}
+
+fn cell(side: Color, (x, y): (i8, i8)) -> String {
+    let y = if side == Color::Black { y } else { 3 - y };
+    format!("{}{}", "CBA".as_bytes()[x as usize] as char, 4 - y)
+}
+
+// the side to move at a given ply
+pub fn side(ply: usize) -> Color {
+    if ply % 2 == 1 { Color::Black } else { Color::White }
+}
+
+// write a move of a board (seen from `side`)
+pub fn write_move(b: Board, side: Color, m: Move, style: Style) -> String {
+    match style {
+        Style::Ascii => {
+            match m.from {
+                Some(from) =>
+                    format!("{}{}{}", cell(side, from), cell(side, m.to), if m.promotion { "+" } else { "" }),
+                None => format!("{}*{}", m.piece.show(), cell(side, m.to)),
+            }
+        }
+        Style::Japanese => {
+            let mut s = String::from(if side == Color::Black { "▲" } else { "△" });
+            s += &cell(side, m.to);
+            s += name(m.piece);
+            let (nx, ny) = m.to;
+            match m.from {
+                Some((x, y)) => {
+                    if m.promotion { return s + "成" }
+
+                    // a white board is mirrored (see Board::reverse), so
+                    // left and right are swapped
+                    let (x, nx) = if side == Color::Black { (x, nx) } else { (2 - x, 2 - nx) };
+
+                    // check ambiguity: is there another move that p to the same cell?
+                    for m2 in b.legal_moves() {
+                        if m2.to != m.to || m2.piece != m.piece || m2.promotion { continue }
+                        let (x2, y2) = match m2.from {
+                            Some((x2, y2)) => (if side == Color::Black { x2 } else { 2 - x2 }, y2),
+                            None => continue,
+                        };
+                        // ignore self
+                        if (x2, y2) == (x, y) { continue }
+
+                        if x == nx && y < ny && y2 < ny {
+                            // both are up moves, and no horizontal move
+                            s += "直";
+                        }
+                        else if y == y2 {
+                            // the vertical direction is the same (or no vertical move)
+                            s += if x < nx { "左" } else { "右" };
+                        }
+                        else if y < ny {
+                            s += "上";
+                        }
+                        else if y > ny {
+                            s += "引";
+                        }
+                        else {
+                            s += "寄";
+                        }
+                    }
+                }
+                None => {
+                    let piece = m.piece;
+                    if b.legal_moves().iter().any(|m2| {
+                        m2.from.is_some() && m2.to == m.to && (if m2.promotion { HEN } else { m2.piece }) == piece
+                    }) {
+                        s += "打";
+                    }
+                }
+            }
+            s
+        }
+    }
+}
+
+// strip the optional parts (the side mark and the suffixes) of a move
+fn strip(s: &str) -> &str {
+    let s = s.trim_start_matches(['▲', '△']);
+    let s = s.trim_end_matches(|c| "直左右上引寄打成".contains(c));
+    s.trim_end_matches('+')
+}
+
+// read a move of a board (seen from `side`)
+pub fn read_move(b: Board, side: Color, s: &str) -> Option<Move> {
+    let style = if s.is_ascii() { Style::Ascii } else { Style::Japanese };
+    let mark = if side == Color::Black { '▲' } else { '△' };
+    if style == Style::Japanese && s.starts_with(['▲', '△']) && !s.starts_with(mark) {
+        return None
+    }
+    let ms = b.legal_moves();
+    let ss: Vec<String> = ms.iter().map(|&m| write_move(b, side, m, style)).collect();
+
+    // an exact match
+    for (i, s2) in ss.iter().enumerate() {
+        if s2.trim_start_matches(mark) == s.trim_start_matches(mark) {
+            return Some(ms[i])
+        }
+    }
+
+    // redundant or missing suffixes are allowed if not ambiguous
+    let cands: Vec<usize> = (0..ms.len()).filter(|&i| strip(&ss[i]) == strip(s)).collect();
+    if cands.len() == 1 { Some(ms[cands[0]]) } else { None }
+}
+
+impl Kifu {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    // replay the record from the initial board, and return all boards (each
+    // seen from the player to move, not normalized)
+    pub fn replay(&self) -> Result<Vec<Board>, KifuError> {
+        let mut b = Board::init();
+        let mut boards = vec![b];
+        for (i, &m) in self.moves.iter().enumerate() {
+            check(b, m, i + 1)?;
+            b = b.apply(m).reverse();
+            boards.push(b);
+        }
+        Ok(boards)
+    }
+
+    pub fn read(s: &str) -> Result<Kifu, KifuError> {
+        let mut kifu = Kifu::new();
+        let mut b = Board::init();
+        for line in s.lines() {
+            if line.trim_start().starts_with('#') { continue }
+            for tok in line.split_whitespace() {
+                let ply = kifu.moves.len() + 1;
+                if over(b) { return Err(KifuError { ply, msg: "the game is already over".to_string() }) }
+                match read_move(b, side(ply), tok) {
+                    Some(m) => {
+                        kifu.moves.push(m);
+                        b = b.apply(m).reverse();
+                    }
+                    None => return Err(KifuError { ply, msg: format!("illegal move: {}", tok) }),
+                }
+            }
+        }
+        Ok(kifu)
+    }
+
+    pub fn write(&self, style: Style) -> Result<String, KifuError> {
+        let boards = self.replay()?;
+        let mut s = String::new();
+        for (i, &m) in self.moves.iter().enumerate() {
+            s += &write_move(boards[i], side(i + 1), m, style);
+            s.push('\n');
+        }
+        Ok(s)
+    }
+}
+
+// check if the game is over, i.e., the player to move has lost the lion or
+// the opponent succeeded "try"
+fn over(b: Board) -> bool {
+    let lion = (0..3).any(|x| (0..4).any(|y| b.get(x, y) == LION));
+    !lion || b.legal_moves().is_empty()
+}
+
+// check if a move is legal
+fn check(b: Board, m: Move, ply: usize) -> Result<(), KifuError> {
+    if over(b) { return Err(KifuError { ply, msg: "the game is already over".to_string() }) }
+    if !b.legal_moves().contains(&m) { return Err(KifuError { ply, msg: format!("illegal move: {:?}", m) }) }
+    Ok(())
+}
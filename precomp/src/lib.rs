@@ -1,8 +1,21 @@
+pub mod bitboard;
 pub mod board;
 pub mod board_collection;
+pub mod compress;
+pub mod kifu;
+pub mod mmap;
+pub mod oracle;
+pub mod perft;
+pub mod phf;
+pub mod position;
+pub mod rank;
+pub mod sfen;
+pub mod tablebase;
 
+use std::env;
 use std::fmt;
-use std::io::{self, BufRead, Write};
+use std::io::{self, BufRead, Read, Write};
+use std::process;
 use board::Board;
 
 // helper for logging
@@ -23,31 +36,527 @@ macro_rules! out(
     } }
 );
 
-pub struct Out(io::BufWriter<io::Stdout>);
+pub struct Out(io::BufWriter<io::Stdout>, Format);
+
+impl Default for Out {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Out {
     pub fn new() -> Self {
-        Out(io::BufWriter::new(io::stdout()))
+        Out(io::BufWriter::new(io::stdout()), Format::Text)
     }
     pub fn out<'a>(&mut self, s: fmt::Arguments<'a>) {
         let r = self.0.write_fmt(s);
         r.expect("failed printing to stdout")
     }
+
+    // start writing records in a given format (the header is written only in
+    // the binary format)
+    pub fn records(format: Format, header: Header) -> Self {
+        let mut out = Out(io::BufWriter::new(io::stdout()), format);
+        if format == Format::Binary {
+            let r = header.write(&mut out.0);
+            r.expect("failed printing to stdout")
+        }
+        out
+    }
+    pub fn record(&mut self, b: Board, depth: i32) {
+        let r = self.1.write(&mut self.0, b, depth);
+        r.expect("failed printing to stdout")
+    }
+    pub fn record_idx(&mut self, b: Board, depth: i32, idx: i32) {
+        let r = self.1.write_idx(&mut self.0, b, depth, idx);
+        r.expect("failed printing to stdout")
+    }
+
+    // start writing a DAG (3.txt) with the names of the start nodes
+    pub fn dag(format: Format, count: u64, start: &[u32]) -> Self {
+        let mut out = Out(io::BufWriter::new(io::stdout()), format);
+        let r = format.write_start(&mut out.0, count, start);
+        r.expect("failed printing to stdout");
+        out
+    }
+    pub fn node(&mut self, node: &DagNode) {
+        let r = self.1.write_node(&mut self.0, node);
+        r.expect("failed printing to stdout")
+    }
+}
+
+// record formats of the intermediate files (1.txt, 2.txt, 4.txt and 9.txt)
+//
+// text: "board depth [idx]\n" (board is a 15-digit hex)
+// binary: a header and fixed-size records
+//
+//   header (24 bytes, little endian):
+//     magic: "\x89dobutsu" (8 bytes)
+//     version: u32
+//     stage: u8 (the stage that wrote the file)
+//     order: u8 (see Order)
+//     idx: u8 (1 if each record has a move index)
+//     reserved: 1 byte (zero)
+//     count: u64 (the number of records, or u64::MAX if unknown)
+//   record (9 or 10 bytes):
+//     board: u64 (little endian)
+//     depth: i8
+//     idx: u8 (only if the header says so)
+//
+// 3.txt (see DagNode) and 5.txt (see phf.rs) start with the same header, and
+// have their own bodies.  Readers detect the format by the first byte, so
+// `--format` is needed only for writers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format { Text, Binary }
+
+pub const MAGIC : &[u8; 8] = b"\x89dobutsu";
+pub const VERSION : u32 = 1;
+pub const HEADER_SIZE : usize = 24;
+pub const RECORD_SIZE : usize = 9;
+
+impl Format {
+    // parse `--format text|binary` (the default is text)
+    pub fn from_args() -> Format {
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg != "--format" { continue }
+            return match args.next().as_deref() {
+                Some("text") => Format::Text,
+                Some("binary") => Format::Binary,
+                v => {
+                    log!("unknown format: {:?} (text or binary)", v);
+                    process::exit(1)
+                }
+            }
+        }
+        Format::Text
+    }
+
+    // write a record
+    pub fn write<W: Write>(self, w: &mut W, b: Board, depth: i32) -> io::Result<()> {
+        match self {
+            Format::Text => writeln!(w, "{:015x} {}", b.0, depth),
+            Format::Binary => {
+                assert!(depth as i8 as i32 == depth, "depth out of range: {}", depth);
+                let mut buf = [0; RECORD_SIZE];
+                buf[..8].copy_from_slice(&b.0.to_le_bytes());
+                buf[8] = depth as i8 as u8;
+                w.write_all(&buf)
+            }
+        }
+    }
+
+    // write a record with a move index (the header must have idx)
+    pub fn write_idx<W: Write>(self, w: &mut W, b: Board, depth: i32, idx: i32) -> io::Result<()> {
+        match self {
+            Format::Text => writeln!(w, "{:015x} {} {}", b.0, depth, idx),
+            Format::Binary => {
+                assert!((0..256).contains(&idx), "index out of range: {}", idx);
+                self.write(w, b, depth)?;
+                w.write_all(&[idx as u8])
+            }
+        }
+    }
+
+    // write the header and the start line of a DAG
+    pub fn write_start<W: Write>(self, w: &mut W, count: u64, start: &[u32]) -> io::Result<()> {
+        match self {
+            Format::Text => {
+                for n in start { write!(w, " {}", n)? }
+                writeln!(w)
+            }
+            Format::Binary => {
+                Header { stage: 3, order: Order::Unsorted, idx: false, count: Some(count) }.write(w)?;
+                write_names(w, start)
+            }
+        }
+    }
+
+    // write a node of a DAG
+    pub fn write_node<W: Write>(self, w: &mut W, node: &DagNode) -> io::Result<()> {
+        match self {
+            Format::Text => {
+                writeln!(w, "{:015x} {} {}", node.board.0, node.depth, node.name)?;
+                for &(idx, ref names) in &node.moves {
+                    write!(w, " {}:", idx)?;
+                    for n in names { write!(w, " {}", n)? }
+                    writeln!(w)?;
+                }
+                writeln!(w)
+            }
+            Format::Binary => {
+                assert!(node.moves.len() < 256, "too many moves: {}", node.moves.len());
+                self.write(w, node.board, node.depth)?;
+                w.write_all(&node.name.to_le_bytes())?;
+                w.write_all(&[node.moves.len() as u8])?;
+                for &(idx, ref names) in &node.moves {
+                    assert!((0..256).contains(&idx), "index out of range: {}", idx);
+                    w.write_all(&[idx as u8])?;
+                    write_names(w, names)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// a list of names: the length (u16) and the names (u32)
+fn write_names<W: Write>(w: &mut W, names: &[u32]) -> io::Result<()> {
+    assert!(names.len() < 65536, "too many names: {}", names.len());
+    w.write_all(&(names.len() as u16).to_le_bytes())?;
+    for n in names { w.write_all(&n.to_le_bytes())? }
+    Ok(())
+}
+
+fn read_names<R: Read>(r: &mut R) -> io::Result<Vec<u32>> {
+    let mut n = [0; 2];
+    r.read_exact(&mut n)?;
+    (0..u16::from_le_bytes(n)).map(|_| {
+        let mut n = [0; 4];
+        r.read_exact(&mut n)?;
+        Ok(u32::from_le_bytes(n))
+    }).collect()
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// the order of records
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Order {
+    Unsorted, // e.g., 1-enum's output (in DFS order)
+    Depth,    // in order of analysis (depth 0, 1, 2, ..., and draws last)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Header {
+    pub stage: u8,
+    pub order: Order,
+    pub idx: bool, // the records have move indexes (4-opt's output)
+    pub count: Option<u64>,
+}
+
+impl Header {
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut buf = [0; HEADER_SIZE];
+        buf[..8].copy_from_slice(MAGIC);
+        buf[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        buf[12] = self.stage;
+        buf[13] = self.order as u8;
+        buf[14] = self.idx as u8;
+        buf[16..].copy_from_slice(&self.count.unwrap_or(u64::MAX).to_le_bytes());
+        w.write_all(&buf)
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Header> {
+        let mut buf = [0; HEADER_SIZE];
+        r.read_exact(&mut buf)?;
+        if &buf[..8] != MAGIC { return Err(invalid("bad magic".to_string())) }
+        let mut n = [0; 4];
+        n.copy_from_slice(&buf[8..12]);
+        let version = u32::from_le_bytes(n);
+        if version != VERSION { return Err(invalid(format!("unsupported version {}", version))) }
+        let order = match buf[13] {
+            0 => Order::Unsorted,
+            1 => Order::Depth,
+            o => return Err(invalid(format!("unknown order {}", o))),
+        };
+        let idx = match buf[14] {
+            0 => false,
+            1 => true,
+            i => return Err(invalid(format!("unknown idx flag {}", i))),
+        };
+        let mut n = [0; 8];
+        n.copy_from_slice(&buf[16..]);
+        let count = u64::from_le_bytes(n);
+        Ok(Header { stage: buf[12], order, idx, count: if count == u64::MAX { None } else { Some(count) } })
+    }
+
+    // the size of a record
+    pub fn record_size(&self) -> usize {
+        if self.idx { RECORD_SIZE + 1 } else { RECORD_SIZE }
+    }
+}
+
+// reader of records in either format
+//
+// Each item is (board, depth, idx) or an error message with the line (or
+// record) number.  idx is 0 if the input has no move indexes.
+pub struct Records<R> {
+    r: R,
+    header: Option<Header>, // None if the format is text
+    check: bool,
+    n: u64, // the number of lines (or records) read
+    line: String,
+}
+
+impl<R: BufRead> Records<R> {
+    pub fn new(mut r: R, check: bool) -> io::Result<Self> {
+        let binary = r.fill_buf()?.first() == Some(&MAGIC[0]);
+        let header = if binary { Some(Header::read(&mut r)?) } else { None };
+        if let Some(h) = header {
+            if h.stage == 3 || h.stage == 5 { return Err(invalid(format!("not records (written by stage {})", h.stage))) }
+        }
+        Ok(Records { r, header, check, n: 0, line: String::new() })
+    }
+
+    pub fn format(&self) -> Format {
+        if self.header.is_some() { Format::Binary } else { Format::Text }
+    }
+
+    pub fn header(&self) -> Option<&Header> {
+        self.header.as_ref()
+    }
+
+    fn next_line(&mut self) -> Option<Result<(Board, i32, i32), String>> {
+        self.line.clear();
+        match self.r.read_line(&mut self.line) {
+            Ok(0) => None,
+            Ok(_) => {
+                let line = self.line.trim_end_matches('\n').trim_end_matches('\r');
+                Some(In::parse(line, self.check).map_err(|msg| format!("line {}: {}: {:?}", self.n, msg, line)))
+            }
+            Err(e) => Some(Err(format!("line {}: {}", self.n, e))),
+        }
+    }
+
+    fn next_record(&mut self, h: Header) -> Option<Result<(Board, i32, i32), String>> {
+        let n = self.n;
+        let eof = match self.r.fill_buf() {
+            Ok(buf) => buf.is_empty(),
+            Err(e) => return Some(Err(format!("record {}: {}", n, e))),
+        };
+        match h.count {
+            Some(count) if n > count => {
+                if eof { return None }
+                return Some(Err(format!("record {}: more records than the header says ({})", n, count)))
+            }
+            Some(count) if eof => {
+                return Some(Err(format!("record {}: truncated (the header says {} records)", n, count)))
+            }
+            None if eof => return None,
+            _ => {}
+        }
+        let mut buf = [0; RECORD_SIZE + 1];
+        if let Err(e) = self.r.read_exact(&mut buf[..h.record_size()]) {
+            return Some(Err(format!("record {}: {}", n, e)))
+        }
+        let mut b = [0; 8];
+        b.copy_from_slice(&buf[..8]);
+        let b = Board(u64::from_le_bytes(b));
+        if self.check {
+            if let Err(e) = b.validate() {
+                return Some(Err(format!("record {}: invalid board: {}", n, e)))
+            }
+        }
+        let idx = if h.idx { buf[9] as i32 } else { 0 };
+        Some(Ok((b, buf[8] as i8 as i32, idx)))
+    }
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = Result<(Board, i32, i32), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.n += 1;
+        match self.header {
+            Some(h) => self.next_record(h),
+            None => self.next_line(),
+        }
+    }
+}
+
+// a node of the DAG that 3-extract writes (3.txt)
+//
+// text: see src/3-extract.rs
+// binary: a header (stage 3, count: the number of nodes), the names of the
+// start nodes (see write_names), and the nodes
+//
+//   node:
+//     board: u64, depth: i8, name: u32
+//     moves: u8 (the number of moves)
+//     for each move: idx: u8, the names of the next nodes
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DagNode {
+    pub board: Board,
+    pub depth: i32,
+    pub name: u32,
+    pub moves: Vec<(i32, Vec<u32>)>, // (move index, the names of the next nodes)
+}
+
+// reader of the nodes of a DAG (3.txt) in either format
+//
+// The names of the start nodes are read first; each item is a node or an
+// error message with the line (or node) number.
+pub struct Nodes<R> {
+    r: R,
+    header: Option<Header>,
+    start: Vec<u32>,
+    n: u64,
+    line: String,
+}
+
+impl<R: BufRead> Nodes<R> {
+    pub fn new(mut r: R) -> io::Result<Self> {
+        let binary = r.fill_buf()?.first() == Some(&MAGIC[0]);
+        let (header, start) = if binary {
+            let h = Header::read(&mut r)?;
+            if h.stage != 3 { return Err(invalid(format!("not a DAG (written by stage {})", h.stage))) }
+            (Some(h), read_names(&mut r)?)
+        }
+        else {
+            let mut line = String::new();
+            r.read_line(&mut line)?;
+            let start = line.split_whitespace().map(|s| s.parse().map_err(|e| invalid(format!("line 1: invalid name: {}", e)))).collect::<io::Result<_>>()?;
+            (None, start)
+        };
+        Ok(Nodes { r, header, start, n: 1, line: String::new() })
+    }
+
+    pub fn format(&self) -> Format {
+        if self.header.is_some() { Format::Binary } else { Format::Text }
+    }
+
+    // the names of the start nodes
+    pub fn start(&self) -> &[u32] {
+        &self.start
+    }
+
+    fn read_line(&mut self) -> Result<Option<&str>, String> {
+        self.n += 1;
+        self.line.clear();
+        match self.r.read_line(&mut self.line) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(self.line.trim_end_matches('\n').trim_end_matches('\r'))),
+            Err(e) => Err(format!("line {}: {}", self.n, e)),
+        }
+    }
+
+    fn next_text(&mut self) -> Option<Result<DagNode, String>> {
+        let n = self.n + 1;
+        let mut node = match self.read_line() {
+            Ok(None) => return None,
+            Ok(Some(line)) => {
+                let split: Vec<&str> = line.split(' ').collect();
+                if split.len() != 3 { return Some(Err(format!("line {}: invalid node: {:?}", n, line))) }
+                match (u64::from_str_radix(split[0], 16), split[1].parse(), split[2].parse()) {
+                    (Ok(b), Ok(depth), Ok(name)) => DagNode { board: Board(b), depth, name, moves: vec![] },
+                    _ => return Some(Err(format!("line {}: invalid node: {:?}", n, line))),
+                }
+            }
+            Err(msg) => return Some(Err(msg)),
+        };
+        loop {
+            let n = self.n + 1;
+            let m = match self.read_line() {
+                Ok(None) => return Some(Err(format!("line {}: unexpected end of input", n))),
+                Ok(Some("")) => return Some(Ok(node)),
+                Ok(Some(line)) => {
+                    let mut split = line.splitn(2, ':');
+                    let idx = split.next().unwrap().trim().parse();
+                    let names = split.next().map(|s| s.split_whitespace().map(|s| s.parse()).collect());
+                    match (idx, names) {
+                        (Ok(idx), Some(Ok(names))) => (idx, names),
+                        _ => return Some(Err(format!("line {}: invalid move: {:?}", n, line))),
+                    }
+                }
+                Err(msg) => return Some(Err(msg)),
+            };
+            node.moves.push(m);
+        }
+    }
+
+    fn next_binary(&mut self, count: Option<u64>) -> Option<Result<DagNode, String>> {
+        self.n += 1;
+        let n = self.n - 1;
+        let eof = match self.r.fill_buf() {
+            Ok(buf) => buf.is_empty(),
+            Err(e) => return Some(Err(format!("node {}: {}", n, e))),
+        };
+        match count {
+            Some(count) if n > count => {
+                if eof { return None }
+                return Some(Err(format!("node {}: more nodes than the header says ({})", n, count)))
+            }
+            Some(count) if eof => {
+                return Some(Err(format!("node {}: truncated (the header says {} nodes)", n, count)))
+            }
+            None if eof => return None,
+            _ => {}
+        }
+        let r = &mut self.r;
+        let node = (|| -> io::Result<DagNode> {
+            let mut buf = [0; RECORD_SIZE + 4 + 1];
+            r.read_exact(&mut buf)?;
+            let mut b = [0; 8];
+            b.copy_from_slice(&buf[..8]);
+            let mut name = [0; 4];
+            name.copy_from_slice(&buf[9..13]);
+            let mut moves = vec![];
+            for _ in 0..buf[13] {
+                let mut idx = [0; 1];
+                r.read_exact(&mut idx)?;
+                moves.push((idx[0] as i32, read_names(r)?));
+            }
+            Ok(DagNode { board: Board(u64::from_le_bytes(b)), depth: buf[8] as i8 as i32, name: u32::from_le_bytes(name), moves })
+        })();
+        Some(node.map_err(|e| format!("node {}: {}", n, e)))
+    }
+}
+
+impl<R: BufRead> Iterator for Nodes<R> {
+    type Item = Result<DagNode, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.header {
+            Some(h) => self.next_binary(h.count),
+            None => self.next_text(),
+        }
+    }
 }
 
 // helper for input
+//
+// Each line is "board depth [idx]", or the input may be binary records (see
+// Format).  If check is true (the programs pass `--check`), each board is
+// also validated (see Board::validate).  The program stops with the line (or
+// record) number of the first corrupt one.
 pub struct In;
 
 impl In {
-    pub fn each<F>(mut f: F) where F: FnMut(Board, i32, i32) {
+    pub fn each<F>(check: bool, mut f: F) where F: FnMut(Board, i32, i32) {
         let stdin = io::stdin();
-        for line in stdin.lock().lines() {
-            let line = line.unwrap();
-            let split: Vec<&str> = line.as_str().split(' ').collect();
-            let b = Board(u64::from_str_radix(split[0], 16).unwrap());
-            let depth = i32::from_str_radix(split[1], 10).unwrap();
-            let idx = if split.len() >= 3 { i32::from_str_radix(split[2], 10).unwrap() } else { 0 };
-            f(b, depth, idx);
+        let records = match Records::new(stdin.lock(), check) {
+            Ok(records) => records,
+            Err(e) => {
+                log!("invalid input: {}", e);
+                process::exit(1)
+            }
+        };
+        for r in records {
+            match r {
+                Ok((b, depth, idx)) => f(b, depth, idx),
+                Err(msg) => {
+                    log!("{}", msg);
+                    process::exit(1)
+                }
+            }
+        }
+    }
+
+    // parse a line ("board depth [idx]")
+    pub fn parse(line: &str, check: bool) -> Result<(Board, i32, i32), String> {
+        let split: Vec<&str> = line.split(' ').collect();
+        if split.len() < 2 { return Err("too few fields".to_string()) }
+        let b = Board(u64::from_str_radix(split[0], 16).map_err(|e| format!("invalid board: {}", e))?);
+        let depth = split[1].parse::<i32>().map_err(|e| format!("invalid depth: {}", e))?;
+        let idx = if split.len() >= 3 {
+            split[2].parse::<i32>().map_err(|e| format!("invalid index: {}", e))?
+        } else { 0 };
+        if check {
+            b.validate().map_err(|e| format!("invalid board: {}", e))?;
         }
+        Ok((b, depth, idx))
     }
 }
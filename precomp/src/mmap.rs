@@ -0,0 +1,89 @@
+// a thin shim of mmap(2) to map a whole file read-only
+//
+// std links libc on unix, so mmap and munmap are declared here instead of
+// depending on a crate.  On the other platforms, the file is just read into
+// memory.
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+pub struct Mmap(Inner);
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+struct Inner {
+    ptr: *const u8,
+    len: usize,
+}
+
+#[cfg(not(all(unix, target_pointer_width = "64")))]
+struct Inner(Vec<u8>);
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod sys {
+    use std::os::raw::{c_int, c_void};
+
+    pub const PROT_READ : c_int = 1;
+    pub const MAP_PRIVATE : c_int = 2;
+
+    extern "C" {
+        pub fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+// the mapping is read-only, so it can be shared among threads
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Mmap> {
+        use std::os::unix::io::AsRawFd;
+        use std::ptr;
+
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 { return Ok(Mmap(Inner { ptr: ptr::null(), len: 0 })) }
+        let ptr = unsafe {
+            sys::mmap(ptr::null_mut(), len, sys::PROT_READ, sys::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        // MAP_FAILED
+        if ptr as isize == -1 { return Err(io::Error::last_os_error()) }
+        Ok(Mmap(Inner { ptr: ptr as *const u8, len }))
+    }
+
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Mmap> {
+        use std::io::Read;
+
+        let mut buf = vec![];
+        File::open(path)?.read_to_end(&mut buf)?;
+        Ok(Mmap(Inner(buf)))
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    fn deref(&self) -> &[u8] {
+        if self.0.len == 0 { return &[] }
+        unsafe { ::std::slice::from_raw_parts(self.0.ptr, self.0.len) }
+    }
+
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    fn deref(&self) -> &[u8] {
+        &self.0 .0
+    }
+}
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.0.len > 0 {
+            unsafe { sys::munmap(self.0.ptr as *mut _, self.0.len); }
+        }
+    }
+}
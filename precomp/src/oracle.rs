@@ -0,0 +1,73 @@
+// an oracle that plays perfectly from the compressed data base (ai.txt)
+//
+// This does the same as AI in client/src/ai.ts: a board whose depth is 3 or
+// less is solved by a shallow search (see Board::easy_next), and the move of
+// a deeper board is looked up from the data base (see phf.rs).
+//
+// Note that the data base has only white boards that are strictly reachable
+// when white follows the oracle.  For other boards, the result is meaningless
+// (or None if the move index is out of range).
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use board::{Board, Status, LION};
+use compress;
+use phf::{self, Phf};
+
+pub struct Oracle {
+    phf: Phf,
+}
+
+impl Oracle {
+    // decode ai.txt
+    pub fn new(buf: &str) -> Option<Self> {
+        compress::decode(buf).map(|phf| Oracle { phf })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let buf = fs::read_to_string(path)?;
+        Oracle::new(&buf).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "broken data base"))
+    }
+
+    // the depth and the move index of a depth-5 (or more) board (normalized)
+    pub fn lookup(&self, b: Board) -> (i32, usize) {
+        let (depth, idx) = phf::unpack(self.phf.get(b));
+        (depth, idx as usize)
+    }
+
+    // given a board seen from the player to move, returns a pair of depth and
+    // the next board (seen from the same player, i.e., not reversed)
+    //
+    // If the opponent's lion can be captured, the next board has no
+    // opponent's lion.  None is returned if the game is already over.
+    pub fn next(&self, b: Board) -> Option<(i32, Board)> {
+        let nb = b.normalize();
+        let flipped = nb != b;
+
+        let ms = nb.legal_moves();
+        let (depth, nnb) = match nb.status() {
+            // we can capture the opponent's lion
+            Status::Win => {
+                let m = ms.into_iter().find(|m| m.capture == LION.opponent())?;
+                (1, nb.apply(m))
+            }
+            Status::Lose => return None,
+            Status::Unknown => {
+                // check if the board is depth-3 or less, and if not, lookup
+                // the data base
+                let (depth, idx) = nb.easy_next().unwrap_or_else(|| self.lookup(nb));
+                (depth, nb.apply(*ms.get(idx)?))
+            }
+        };
+
+        // invert the normalization
+        Some((depth, if flipped { nnb.flip() } else { nnb }))
+    }
+
+    // the same as AI.search: given a board seen from black (white to move),
+    // returns a pair of depth and the next board seen from black
+    pub fn search(&self, b: Board) -> Option<(i32, Board)> {
+        self.next(b.reverse()).map(|(depth, nb)| (depth, nb.reverse()))
+    }
+}
@@ -0,0 +1,48 @@
+// perft: count the nodes of the game tree to validate the move generator
+//
+// The boards are enumerated by Board::each_next, so a board where the player
+// can capture the opponent's lion (or the opponent has succeeded "try") has
+// no next board.
+
+use board::{Board, Move, Status};
+use board_collection::BoardSet;
+
+// count the leaf nodes at a given depth
+pub fn perft(b: Board, depth: usize) -> u64 {
+    if depth == 0 { return 1 }
+    let mut n = 0;
+    b.each_next(|nb| {
+        n += perft(nb, depth - 1);
+        true
+    });
+    n
+}
+
+// count the distinct leaf nodes at a given depth (transpositions and mirror
+// images are merged by Board::normalize)
+pub fn perft_merged(b: Board, depth: usize) -> u64 {
+    let mut boards = BoardSet::new();
+    boards.insert(b.normalize());
+    for _ in 0..depth {
+        let mut next_boards = BoardSet::new();
+        for b in &boards {
+            b.each_next(|nb| {
+                next_boards.insert(nb);
+                true
+            });
+        }
+        boards = next_boards;
+    }
+    boards.len() as u64
+}
+
+// count the leaf nodes for each legal move (in the order of
+// Board::legal_moves); a board that is already won or lost has no next board,
+// and thus no move to count
+pub fn divide(b: Board, depth: usize, merged: bool) -> Vec<(Move, u64)> {
+    if depth == 0 || b.status() != Status::Unknown { return vec![] }
+    b.legal_moves().into_iter().map(|m| {
+        let nb = b.apply(m).reverse().normalize();
+        (m, if merged { perft_merged(nb, depth - 1) } else { perft(nb, depth - 1) })
+    }).collect()
+}
@@ -0,0 +1,262 @@
+// perfect hash function (PHF) for the data base, mapping from board to (depth,
+// move index)
+//
+// ref: http://www.itu.dk/people/pagh/papers/simpleperf.pdf
+//
+// A board has three hashes, board % p_i (+ the offset of the i-th sub-table),
+// for three seed primes.  Each slot of the table has a packed value and an
+// assignment (0, 1 or 2): slot = value * 3 + assignment.  The sum of the
+// assignments of the three slots (mod 3) selects the slot that holds the
+// value of the board.  This is the same as AI.lookup_db in client/src/ai.ts.
+//
+// This is a port of 5-phf.rb; it builds the same table (see build).
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use board::Board;
+use {Format, Header, Order, MAGIC};
+
+// the range of the data base
+pub const MIN_DEPTH : i32 = 5;
+pub const MAX_DEPTH : i32 = 77;
+pub const MAX_IDX   : i32 = 34;
+
+pub struct Phf {
+    pub ps: [u64; 3],    // the seed primes
+    pub table: Vec<u32>, // value * 3 + assignment
+}
+
+// (depth, idx) => value
+pub fn pack(depth: i32, idx: i32) -> u32 {
+    assert!((MIN_DEPTH..=MAX_DEPTH).contains(&depth) && depth % 2 == 1 && (0..MAX_IDX).contains(&idx));
+    ((depth - MIN_DEPTH) / 2 * MAX_IDX + idx) as u32
+}
+
+// value => (depth, idx)
+pub fn unpack(v: u32) -> (i32, i32) {
+    let v = v as i32;
+    (v / MAX_IDX * 2 + MIN_DEPTH, v % MAX_IDX)
+}
+
+// the seed hash functions
+fn hashes(ps: &[u64; 3], b: Board) -> [usize; 3] {
+    let mut hs = [0; 3];
+    let mut c = 0;
+    for i in 0..3 {
+        hs[i] = (b.0 % ps[i] + c) as usize;
+        c += ps[i];
+    }
+    hs
+}
+
+// the first n primes
+fn primes(n: usize) -> Vec<u64> {
+    let mut ps = vec![];
+    let mut size = 16;
+    while ps.len() < n {
+        size *= 2;
+        let mut sieve = vec![true; size];
+        ps.clear();
+        for i in 2..size {
+            if !sieve[i] { continue }
+            ps.push(i as u64);
+            let mut j = i * i;
+            while j < size { sieve[j] = false; j += i }
+        }
+    }
+    ps.truncate(n);
+    ps
+}
+
+// mapping step: check if given three primes are useful
+//
+// Returns the indexes of the boards in the order that they are peeled off;
+// the primes are useful if all boards are peeled off.
+fn check_seed(ps: &[u64; 3], boards: &[(Board, u32)]) -> Vec<usize> {
+    let size = (ps[0] + ps[1] + ps[2]) as usize;
+    let mut t: Vec<Option<Vec<usize>>> = vec![None; size];
+    let mut order = vec![]; // the slots in order of first use
+    for (i, &(b, _)) in boards.iter().enumerate() {
+        for &h in hashes(ps, b).iter() {
+            if t[h].is_none() {
+                t[h] = Some(vec![]);
+                order.push(h);
+            }
+            t[h].as_mut().unwrap().push(i);
+        }
+    }
+
+    let mut stack: Vec<usize> = order.into_iter().filter(|&h| t[h].as_ref().unwrap().len() == 1).collect();
+
+    let mut s = vec![];
+    while let Some(h) = stack.pop() {
+        let i = match t[h].take() { Some(a) => a[0], None => continue };
+        s.push(i);
+        for &h in hashes(ps, boards[i].0).iter() {
+            let n = match t[h] {
+                Some(ref mut a) => { a.retain(|&j| j != i); a.len() }
+                None => continue,
+            };
+            match n {
+                0 => t[h] = None,
+                1 => stack.push(h),
+                _ => {}
+            }
+        }
+    }
+    s
+}
+
+// assigning step: build PHF
+fn build_phf(ps: [u64; 3], boards: &[(Board, u32)], s: &[usize]) -> Phf {
+    let size = (ps[0] + ps[1] + ps[2]) as usize;
+    let mut assign = vec![0; size];
+    let mut visited = vec![false; size];
+    for &i in s.iter().rev() {
+        let vs = hashes(&ps, boards[i].0);
+        for (k, &h) in vs.iter().enumerate() {
+            if visited[h] { continue }
+            let sum: u32 = vs.iter().map(|&v| assign[v]).sum();
+            assign[h] = (k as u32 + 3 * 3 - sum % 3) % 3;
+            break;
+        }
+        for &v in vs.iter() { visited[v] = true }
+    }
+
+    let mut values = vec![None; size];
+    for &(b, v) in boards {
+        let vs = hashes(&ps, b);
+        let h = vs[(vs.iter().map(|&v| assign[v]).sum::<u32>() % 3) as usize];
+        assert!(values[h].is_none());
+        values[h] = Some(v);
+    }
+
+    let table = values.iter().zip(assign.iter()).map(|(v, a)| v.unwrap_or(0) * 3 + a).collect();
+    let phf = Phf { ps, table };
+
+    // check
+    for &(b, v) in boards {
+        assert_eq!(phf.get(b), v);
+    }
+
+    phf
+}
+
+// build a PHF for a list of boards and their values
+//
+// Small three consecutive primes are searched by binary search.  The peeling
+// does not succeed monotonically in the primes, so the search probes exactly
+// the same indexes as `(1..primes.size).bsearch` of 5-phf.rb (find-minimum
+// mode of Range#bsearch).  A seed that runs off the end of the primes (Ruby
+// would try fewer than three primes) is never useful.
+pub fn build(boards: &[(Board, u32)]) -> Option<Phf> {
+    let primes = primes(boards.len() / 2);
+    let seed = |m: usize| {
+        if m + 3 > primes.len() { return None }
+        Some([primes[m], primes[m + 1], primes[m + 2]])
+    };
+    let check = |m: usize| {
+        seed(m).is_some_and(|ps| check_seed(&ps, boards).len() == boards.len())
+    };
+
+    // determine small three primes by using binary search
+    let (mut low, mut high) = (1, primes.len());
+    let mut found = None;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if check(mid) {
+            high = mid;
+            found = Some(mid);
+        }
+        else {
+            low = mid + 1;
+        }
+    }
+    if low == primes.len() {
+        found = if check(low) { Some(low) } else { None };
+    }
+
+    let ps = seed(found?)?;
+    let s = check_seed(&ps, boards);
+    Some(build_phf(ps, boards, &s))
+}
+
+impl Phf {
+    // the value of a board (a meaningless value if the board is not in the
+    // data base)
+    pub fn get(&self, b: Board) -> u32 {
+        let hs = hashes(&self.ps, b);
+        let i = hs.iter().map(|&h| self.table[h]).sum::<u32>() % 3;
+        self.table[hs[i as usize]] / 3
+    }
+
+    // the ratio of the number of boards to the table size
+    pub fn load_factor(&self, n: usize) -> f64 {
+        n as f64 / self.table.len() as f64
+    }
+
+    // parse the output of 5-phf ("[[p1, p2, p3], [slot, ...]]")
+    pub fn parse(s: &str) -> Option<Phf> {
+        let s = s.trim().trim_start_matches("[[").trim_end_matches("]]");
+        let mut split = s.splitn(2, "], [");
+        let ps: Vec<u64> = split.next()?.split(", ").map(|n| n.parse().ok()).collect::<Option<_>>()?;
+        let table = split.next()?.split(", ").map(|n| n.parse().ok()).collect::<Option<_>>()?;
+        if ps.len() != 3 { return None }
+        Some(Phf { ps: [ps[0], ps[1], ps[2]], table })
+    }
+
+    // write the output of 5-phf: the text is "[[p1, p2, p3], [slot, ...]]",
+    // and the binary is a header (stage 5, count: the table size), the primes
+    // (u64) and the slots (u32)
+    pub fn write<W: Write>(&self, w: &mut W, format: Format) -> io::Result<()> {
+        match format {
+            Format::Text => writeln!(w, "{}", self),
+            Format::Binary => {
+                Header { stage: 5, order: Order::Unsorted, idx: false, count: Some(self.table.len() as u64) }.write(w)?;
+                for p in &self.ps { w.write_all(&p.to_le_bytes())? }
+                for v in &self.table { w.write_all(&v.to_le_bytes())? }
+                Ok(())
+            }
+        }
+    }
+
+    // read the output of 5-phf in either format
+    pub fn read<R: BufRead>(mut r: R) -> io::Result<Phf> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        if r.fill_buf()?.first() != Some(&MAGIC[0]) {
+            let mut s = String::new();
+            r.read_to_string(&mut s)?;
+            return Phf::parse(&s).ok_or_else(|| invalid("invalid table"))
+        }
+        let h = Header::read(&mut r)?;
+        if h.stage != 5 { return Err(invalid("not a table of 5-phf")) }
+        let count = h.count.ok_or_else(|| invalid("unknown table size"))?;
+        let mut ps = [0; 3];
+        for p in ps.iter_mut() {
+            let mut n = [0; 8];
+            r.read_exact(&mut n)?;
+            *p = u64::from_le_bytes(n);
+        }
+        if ps.iter().sum::<u64>() != count { return Err(invalid("the table size does not match the primes")) }
+        let mut table = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let mut n = [0; 4];
+            r.read_exact(&mut n)?;
+            table.push(u32::from_le_bytes(n));
+        }
+        if !r.fill_buf()?.is_empty() { return Err(invalid("garbage after the table")) }
+        Ok(Phf { ps, table })
+    }
+}
+
+// the same as `p [ps, a]` of Ruby
+impl fmt::Display for Phf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[[{}, {}, {}], [", self.ps[0], self.ps[1], self.ps[2])?;
+        for (i, v) in self.table.iter().enumerate() {
+            if i > 0 { write!(f, ", ")? }
+            write!(f, "{}", v)?;
+        }
+        write!(f, "]]")
+    }
+}
@@ -0,0 +1,164 @@
+// a position in the absolute orientation
+//
+// Board::next returns boards that are reversed (seen from the next player) and
+// normalized (possibly mirrored).  Position keeps track of the player to move
+// and the mirror state so that the real orientation of the game is kept.
+
+use std::fmt;
+use std::str::FromStr;
+use board::{Board, Color, Move, LION};
+use board_collection::BoardMap;
+use sfen;
+
+// the game is drawn when the same position appears `times` times
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RepetitionRule {
+    pub times: usize,
+}
+
+impl Default for RepetitionRule {
+    fn default() -> Self {
+        RepetitionRule { times: 3 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome { Win(Color), Draw }
+
+// a repetition cycle of a line
+//   start: the number of plies until the line enters the cycle
+//   len: the number of plies of the cycle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    pub start: usize,
+    pub len: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    board: Board,                // seen from the player to move (not normalized)
+    side: Color,                 // the player to move
+    history: Vec<(Board, Move)>, // boards before each move, and the moves
+}
+
+impl Position {
+    pub fn new(board: Board, side: Color) -> Self {
+        Position { board, side, history: vec![] }
+    }
+
+    pub fn init() -> Self {
+        Self::new(Board::init(), Color::Black)
+    }
+
+    // the board seen from the player to move
+    pub fn board(&self) -> Board {
+        self.board
+    }
+
+    // the board which Board::next and the data bases use
+    pub fn canonical(&self) -> Board {
+        self.board.normalize()
+    }
+
+    // check if the canonical board is the mirror image of the real board
+    pub fn mirrored(&self) -> bool {
+        self.canonical() != self.board
+    }
+
+    // the board seen from black
+    pub fn absolute(&self) -> Board {
+        if self.side == Color::Black { self.board } else { self.board.reverse() }
+    }
+
+    pub fn side(&self) -> Color {
+        self.side
+    }
+
+    // the number of moves made so far
+    pub fn ply(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn history(&self) -> Vec<Move> {
+        self.history.iter().map(|&(_, m)| m).collect()
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.board.legal_moves()
+    }
+
+    // make a move (no legality check)
+    pub fn make(&mut self, m: Move) {
+        self.history.push((self.board, m));
+        self.board = self.board.apply(m).reverse();
+        self.side = self.side.opponent();
+    }
+
+    // make a move that leads to one of the boards that Board::next returns
+    // for the canonical board
+    pub fn make_next(&mut self, nb: Board) -> Option<Move> {
+        let m = self.legal_moves().into_iter().find(|&m| self.board.apply(m).reverse().normalize() == nb);
+        if let Some(m) = m { self.make(m) }
+        m
+    }
+
+    // the number of times the current position has appeared (including now)
+    pub fn repetitions(&self) -> usize {
+        let n = self.history.len();
+        let count = self.history.iter().enumerate().filter(|&(i, &(b, _))| {
+            (n - i) % 2 == 0 && b == self.board
+        }).count();
+        count + 1
+    }
+
+    // the result of the game, or None if the game is not over
+    pub fn outcome(&self, rule: RepetitionRule) -> Option<Outcome> {
+        let lion = (0..3).any(|x| (0..4).any(|y| self.board.get(x, y) == LION));
+        if !lion || self.board.legal_moves().is_empty() {
+            return Some(Outcome::Win(self.side.opponent()))
+        }
+        if self.repetitions() >= rule.times { return Some(Outcome::Draw) }
+        None
+    }
+
+    // follow the line that `best` chooses from the current position, and find
+    // its repetition cycle if the line does not end
+    //
+    // `best` takes a canonical board and returns one of its next boards (as
+    // Board::next returns), or None if the game ends.
+    pub fn find_cycle<F>(&self, mut best: F) -> Option<Cycle> where F: FnMut(Board) -> Option<Board> {
+        let mut visited = BoardMap::new();
+        let mut b = self.canonical();
+        let mut ply = 0;
+        while !visited.contains(b) {
+            visited[b] = ply;
+            b = best(b)?;
+            ply += 1;
+        }
+        let start = visited[b] as usize;
+        Some(Cycle { start, len: ply as usize - start })
+    }
+
+    // undo the last move
+    pub fn unmake(&mut self) -> Option<Move> {
+        self.history.pop().map(|(b, m)| {
+            self.board = b;
+            self.side = self.side.opponent();
+            m
+        })
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", sfen::to_string(self.board, self.side))
+    }
+}
+
+impl FromStr for Position {
+    type Err = sfen::ParseError;
+
+    fn from_str(s: &str) -> Result<Position, sfen::ParseError> {
+        sfen::parse(s).map(|(b, side)| Position::new(b, side))
+    }
+}
@@ -0,0 +1,194 @@
+// Rank all valid boards into dense integers (0..N), and vice versa.
+//
+// A board and its mirror image (see Board::normalize) have the same rank, so a
+// flat array indexed by rank can be used instead of BoardSet or BoardMap.
+//
+// A board is decomposed into the following parts, each of which is numbered
+// by the combinatorial number system:
+//
+//   (1) the cells of the two lions (the mirror image is chosen so that the
+//       player's lion is not in the column x = 2, and the opponent's lion is
+//       not in x = 2 if the player's lion is in x = 1; 72 patterns)
+//   (2) for elephants, giraffes, and chicks in order: the remaining cells
+//       that have the pieces of the kind, their owners (and promotion), and
+//       the number of pieces in the player's hand
+//
+// Only if both lions are in the column x = 1, a board and its mirror image
+// are distinguished by (1), so a few ranks are never used.
+
+use board::Board;
+
+const KINDS    : [u64; 3] = [2, 3, 4]; // elephant, giraffe, chick
+const VARIANTS : [u64; 3] = [2, 2, 4]; // owner (and promotion for chicks)
+
+const HEN : u64 = 5;
+
+const fn binom(f: u64, n: u64) -> u64 {
+    match n {
+        0 => 1,
+        1 => f,
+        _ => f * (f - 1) / 2,
+    }
+}
+
+// the number of patterns that the pieces of KINDS[k..] are placed in f cells
+const fn count(k: usize, f: u64) -> u64 {
+    if k == 3 { return 1 }
+    let mut sum = 0;
+    let mut n = 0;
+    while n <= 2 && n <= f {
+        sum += size(k, f, n);
+        n += 1;
+    }
+    sum
+}
+
+// the number of patterns that n pieces of KINDS[k] are on board
+const fn size(k: usize, f: u64, n: u64) -> u64 {
+    binom(f, n) * VARIANTS[k].pow(n as u32) * (3 - n) * count(k + 1, f - n)
+}
+
+// the cells of the player's lion and the opponent's lion
+static LIONS : [(u64, u64); 72] = lions();
+
+const fn lions() -> [(u64, u64); 72] {
+    let mut t = [(0, 0); 72];
+    let mut n = 0;
+    let mut m = 0;
+    while m < 8 {
+        let mut o = 0;
+        while o < 12 {
+            if o != m && (m < 4 || o < 8) {
+                t[n] = (m, o);
+                n += 1;
+            }
+            o += 1;
+        }
+        m += 1;
+    }
+    t
+}
+
+// the number of ranks
+pub const N : u64 = 72 * count(0, 10);
+
+#[inline]
+fn nibble(b: Board, i: u64) -> u64 {
+    (b.0 >> (i * 4)) & 0xf
+}
+
+#[inline]
+fn hands(b: Board, k: usize) -> (u64, u64) {
+    let k = KINDS[k];
+    ((b.0 >> (44 + k * 2)) & 3, (b.0 >> (50 + k * 2)) & 3)
+}
+
+// the cells of the two lions
+fn find_lions(b: Board) -> Option<(u64, u64)> {
+    let (mut m, mut o) = (None, None);
+    for i in 0..12 {
+        match nibble(b, i) {
+            1 if m.is_none() => m = Some(i),
+            9 if o.is_none() => o = Some(i),
+            1 | 9 => return None,
+            _ => {}
+        }
+    }
+    Some((m?, o?))
+}
+
+// returns None if the board does not have the right set of pieces
+pub fn rank(b: Board) -> Option<u64> {
+    let (m, o) = find_lions(b)?;
+    let b = if m / 4 == 2 || (m / 4 == 1 && o / 4 == 2) { b.flip() }
+            else if m / 4 == 1 && o / 4 == 1 { b.normalize() }
+            else { b };
+    let (m, o) = find_lions(b)?;
+    let mut r = LIONS.iter().position(|&l| l == (m, o))? as u64 * count(0, 10);
+
+    let mut free: Vec<u64> = (0..12).filter(|&i| i != m && i != o).collect();
+    for k in 0..3 {
+        let f = free.len() as u64;
+        let v = VARIANTS[k];
+
+        // the pieces on board
+        let mut n = 0;
+        let mut pos = [0; 2];
+        let mut state = 0;
+        for (i, &c) in free.iter().enumerate() {
+            let p = nibble(b, c);
+            if p & 7 == KINDS[k] || (k == 2 && p & 7 == HEN) {
+                if n == 2 { return None }
+                pos[n] = i as u64;
+                state = state * v + if k == 2 { (p >> 3) * 2 + (p & 7 == HEN) as u64 } else { p >> 3 };
+                n += 1;
+            }
+        }
+        let n = n as u64;
+
+        // the pieces in hand
+        let (h1, h2) = hands(b, k);
+        if n + h1 + h2 != 2 { return None }
+
+        let comb = match n { 0 => 0, 1 => pos[0], _ => binom(pos[1], 2) + pos[0] };
+        let local = (comb * v.pow(n as u32) + state) * (3 - n) + h1;
+        for n2 in 0..n { r += size(k, f, n2) }
+        r += local * count(k + 1, f - n);
+
+        for i in (0..n as usize).rev() { free.remove(pos[i] as usize); }
+    }
+
+    // no other piece is allowed
+    if free.iter().any(|&c| nibble(b, c) != 0) { return None }
+
+    Some(r)
+}
+
+// returns a normalized board, or None if r >= N
+pub fn unrank(r: u64) -> Option<Board> {
+    if r >= N { return None }
+    let (m, o) = LIONS[(r / count(0, 10)) as usize];
+    let mut r = r % count(0, 10);
+    let mut b = (1 << (m * 4)) | (9 << (o * 4));
+
+    let mut free: Vec<u64> = (0..12).filter(|&i| i != m && i != o).collect();
+    for k in 0..3 {
+        let f = free.len() as u64;
+        let v = VARIANTS[k];
+
+        let mut n = 0;
+        while r >= size(k, f, n) {
+            r -= size(k, f, n);
+            n += 1;
+        }
+        let local = r / count(k + 1, f - n);
+        r %= count(k + 1, f - n);
+
+        let h1 = local % (3 - n);
+        let local = local / (3 - n);
+        let mut state = local % v.pow(n as u32);
+        let comb = local / v.pow(n as u32);
+
+        let mut pos = [0; 2];
+        match n {
+            0 => {}
+            1 => pos[0] = comb,
+            _ => {
+                let mut p1 = 1;
+                while binom(p1 + 1, 2) <= comb { p1 += 1 }
+                pos = [comb - binom(p1, 2), p1];
+            }
+        }
+        for i in (0..n as usize).rev() {
+            let s = state % v;
+            state /= v;
+            let p = if k == 2 { (s >> 1) * 8 + if s & 1 != 0 { HEN } else { KINDS[k] } } else { s * 8 + KINDS[k] };
+            b |= p << (free.remove(pos[i] as usize) * 4);
+        }
+
+        b += h1 << (44 + KINDS[k] * 2);
+        b += (2 - n - h1) << (50 + KINDS[k] * 2);
+    }
+
+    Some(Board(b).normalize())
+}
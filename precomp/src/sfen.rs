@@ -0,0 +1,141 @@
+// a human-readable position notation (like SFEN of Shogi)
+//
+//   "gle/1c1/1C1/ELG b -"  (the initial board)
+//
+//   rows: from rank 1 (white's side) to rank 4 (black's side), each from
+//         file A to file C, separated by '/'
+//   pieces: L, E, G, C, H (upper case: black, lower case: white)
+//   digits: the number of consecutive empty cells
+//   side: b (black to move) or w (white to move)
+//   hands: the pieces in hand like "E2c", or "-" if nobody has any piece
+//
+// A Board is always seen from the player to move, so a board of white's turn
+// is written after reversing it.
+
+use std::fmt;
+use std::str::FromStr;
+use board::{Board, Color, Piece, EMPTY, LION, ELEPHANT, GIRAFFE, CHICK, HEN};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid position: {}", self.0)
+    }
+}
+
+macro_rules! fail(
+    ($($arg:tt)*) => { return Err(ParseError(format!($($arg)*))) }
+);
+
+fn piece(c: char) -> Option<Piece> {
+    let p = match c.to_ascii_uppercase() {
+        'L' => LION,
+        'E' => ELEPHANT,
+        'G' => GIRAFFE,
+        'C' => CHICK,
+        'H' => HEN,
+        _ => return None,
+    };
+    Some(if c.is_ascii_uppercase() { p } else { p.opponent() })
+}
+
+// render a board whose player to move is `side`
+pub fn to_string(b: Board, side: Color) -> String {
+    let b = if side == Color::Black { b } else { b.reverse() };
+    let mut s = String::new();
+    for y in (0..4).rev() {
+        let mut empty = 0;
+        for x in (0..3).rev() {
+            let p = b.get(x, y);
+            if p == EMPTY {
+                empty += 1;
+                continue
+            }
+            if empty > 0 { s.push_str(&empty.to_string()) }
+            empty = 0;
+            s.push(p.show());
+        }
+        if empty > 0 { s.push_str(&empty.to_string()) }
+        if y > 0 { s.push('/') }
+    }
+    s.push_str(if side == Color::Black { " b " } else { " w " });
+    let mut hands = String::new();
+    for &p in [ELEPHANT, GIRAFFE, CHICK, ELEPHANT.opponent(), GIRAFFE.opponent(), CHICK.opponent()].iter() {
+        match b.hand(p) {
+            0 => {}
+            1 => hands.push(p.show()),
+            n => hands.push_str(&format!("{}{}", n, p.show())),
+        }
+    }
+    s.push_str(if hands.is_empty() { "-" } else { &hands });
+    s
+}
+
+// parse a position, returning the board (seen from the player to move) and
+// the player to move
+pub fn parse(s: &str) -> Result<(Board, Color), ParseError> {
+    let fields: Vec<&str> = s.split_whitespace().collect();
+    if fields.len() != 3 { fail!("expected 3 fields: {:?}", s) }
+
+    let rows: Vec<&str> = fields[0].split('/').collect();
+    if rows.len() != 4 { fail!("expected 4 rows: {:?}", fields[0]) }
+    let mut b = Board(0);
+    for (i, row) in rows.iter().enumerate() {
+        let y = 3 - i as i8;
+        let mut x = 3;
+        for c in row.chars() {
+            if let Some(n) = c.to_digit(10) {
+                x -= n as i8;
+                continue
+            }
+            x -= 1;
+            if x < 0 { break }
+            match piece(c) {
+                Some(p) => b = b.put(x, y, p),
+                None => fail!("unknown piece: {:?}", c),
+            }
+        }
+        if x != 0 { fail!("expected 3 cells: {:?}", row) }
+    }
+
+    let side = match fields[1] {
+        "b" => Color::Black,
+        "w" => Color::White,
+        _ => fail!("unknown side: {:?}", fields[1]),
+    };
+
+    if fields[2] != "-" {
+        let mut n = 1;
+        for c in fields[2].chars() {
+            if let Some(d) = c.to_digit(10) {
+                n = d as i8;
+                continue
+            }
+            let p = match piece(c) {
+                Some(p) if p != LION && p != LION.opponent() && p != HEN && p != HEN.opponent() => p,
+                _ => fail!("unknown piece in hand: {:?}", c),
+            };
+            if b.hand(p) + n > 3 { fail!("too many pieces in hand: {:?}", c) }
+            for _ in 0..n {
+                b = if p.mine() { b.inc_hand(p) } else { b.reverse().inc_hand(p.opponent()).reverse() };
+            }
+            n = 1;
+        }
+    }
+
+    Ok((if side == Color::Black { b } else { b.reverse() }, side))
+}
+
+// accepts either the notation above or the hex form of Display
+impl FromStr for Board {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Board, ParseError> {
+        if s.len() == 15 {
+            if let Ok(n) = u64::from_str_radix(s, 16) { return Ok(Board(n)) }
+        }
+        parse(s).map(|(b, _)| b)
+    }
+}
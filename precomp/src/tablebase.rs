@@ -0,0 +1,176 @@
+// a tablebase over 2-analyze's output
+//
+// 2-analyze determines the depth of every reachable board (seen from the
+// player to move, normalized):
+//
+//   odd: the player wins in `depth` plies
+//   even: the player loses in `depth` plies
+//   -1: draw
+//
+// Unlike the oracle (see oracle.rs), the tablebase can be probed for any
+// reachable board of either side.
+//
+// Reading 2.txt takes minutes, so the tablebase can be saved as an image of
+// BoardMap, which is mapped into memory instantly (see board_collection.rs).
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use board::{Board, Move, LION};
+use board_collection::{self, BoardMap, MapImage};
+use Records;
+
+// the value of a board for the player to move
+//   Win(n): the player wins in n plies
+//   Loss(n): the player loses in n plies
+//
+// Values are ordered from the player's point of view (greater is better): a
+// quicker win, a draw, and then a slower loss.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Value { Win(i32), Loss(i32), Draw }
+
+impl Value {
+    fn from_depth(depth: i32) -> Value {
+        if depth < 0 { Value::Draw } else if depth % 2 == 1 { Value::Win(depth) } else { Value::Loss(depth) }
+    }
+
+    // the value of the previous board (seen from the previous player)
+    fn prev(self) -> Value {
+        match self {
+            Value::Win(n) => Value::Loss(n + 1),
+            Value::Loss(n) => Value::Win(n + 1),
+            Value::Draw => Value::Draw,
+        }
+    }
+
+    // a key to order values
+    fn key(self) -> (i32, i32) {
+        match self {
+            Value::Win(n) => (1, -n),
+            Value::Draw => (0, 0),
+            Value::Loss(n) => (-1, n),
+        }
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Value) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// the depths read into memory, or mapped from an image
+enum Depths {
+    Map(BoardMap<i8>),
+    Image(MapImage<i8>),
+}
+
+pub struct Tablebase {
+    depths: Depths,
+}
+
+impl Default for Tablebase {
+    fn default() -> Self {
+        Tablebase { depths: Depths::Map(BoardMap::new()) }
+    }
+}
+
+impl Tablebase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match self.depths {
+            Depths::Map(ref m) => m.len(),
+            Depths::Image(ref img) => img.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the depths in memory (an image is read into memory first)
+    fn map(&mut self) -> &mut BoardMap<i8> {
+        if let Depths::Image(ref img) = self.depths {
+            self.depths = Depths::Map(img.iter().collect());
+        }
+        match self.depths {
+            Depths::Map(ref mut m) => m,
+            Depths::Image(_) => unreachable!(),
+        }
+    }
+
+    pub fn insert(&mut self, b: Board, depth: i32) {
+        self.map()[b.normalize()] = depth as i8;
+    }
+
+    // read 2-analyze's output (in either format)
+    pub fn read<R: BufRead>(r: R) -> io::Result<Self> {
+        let mut tb = Tablebase::new();
+        for r in Records::new(r, false)? {
+            match r {
+                Ok((b, depth, _)) => tb.insert(b, depth),
+                Err(msg) => return Err(io::Error::new(io::ErrorKind::InvalidData, msg)),
+            }
+        }
+        Ok(tb)
+    }
+
+    // read 2-analyze's output, or map an image (see save)
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        if board_collection::is_image(&path) {
+            return Ok(Tablebase { depths: Depths::Image(MapImage::open(path)?) })
+        }
+        Tablebase::read(BufReader::new(File::open(path)?))
+    }
+
+    // save the depths as an image (3-extract --image also accepts it)
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        match self.depths {
+            Depths::Map(ref m) => m.save(path),
+            Depths::Image(ref img) => img.iter().collect::<BoardMap<i8>>().save(path),
+        }
+    }
+
+    // the depth of a board (seen from the player to move), or None if the
+    // board is not reachable
+    pub fn depth(&self, b: Board) -> Option<i32> {
+        let b = b.normalize();
+        match self.depths {
+            Depths::Map(ref m) => m.get(b).map(|&d| d as i32),
+            Depths::Image(ref img) => img.get(b).map(|d| d as i32),
+        }
+    }
+
+    pub fn probe(&self, b: Board) -> Option<Value> {
+        self.depth(b).map(Value::from_depth)
+    }
+
+    // the value of a move (seen from the player who makes the move)
+    pub fn probe_move(&self, b: Board, m: Move) -> Option<Value> {
+        if m.capture == LION.opponent() { return Some(Value::Win(1)) }
+        self.probe(b.apply(m).reverse()).map(Value::prev)
+    }
+
+    // all moves that have the optimal value (in the order of
+    // Board::legal_moves)
+    pub fn best_moves(&self, b: Board) -> Vec<Move> {
+        let ms: Vec<(Move, Value)> = b.legal_moves().into_iter().filter_map(|m| {
+            self.probe_move(b, m).map(|v| (m, v))
+        }).collect();
+        let best = match ms.iter().map(|&(_, v)| v).max() {
+            Some(best) => best,
+            None => return vec![],
+        };
+        ms.into_iter().filter(|&(_, v)| v == best).map(|(m, _)| m).collect()
+    }
+}
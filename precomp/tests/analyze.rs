@@ -0,0 +1,53 @@
+extern crate precomp;
+
+mod common;
+
+use std::fs;
+use common::{analyze, enumerate, tmp_dir};
+
+#[test]
+fn threads_and_counters() {
+    // the output is the same except the order of boards of the same depth
+    fn sorted(out: Vec<u8>) -> Vec<(usize, String)> {
+        let s = String::from_utf8(out).unwrap();
+        let mut lines = vec![];
+        let mut depth = "0";
+        let mut group = 0;
+        for line in s.lines() {
+            let d = line.split(' ').nth(1).unwrap();
+            if d != depth { depth = d; group += 1 }
+            lines.push((group, line.to_string()));
+        }
+        lines.sort();
+        lines
+    }
+    let (_, input) = enumerate();
+    let out = sorted(analyze(&input, &["--threads", "1"]));
+    for &n in ["2", "3", "8"].iter() {
+        assert_eq!(sorted(analyze(&input, &["--threads", n])), out);
+        assert_eq!(sorted(analyze(&input, &["--threads", n, "--counter"])), out);
+    }
+}
+
+#[test]
+fn checkpoint_and_resume() {
+    let dir = tmp_dir("checkpoint");
+    let (_, input) = enumerate();
+    let ckpt = dir.to_str().unwrap();
+    for args in [&[][..], &["--counter"][..], &["--format", "binary"][..]].iter() {
+        let out = analyze(&input, args);
+        let arg = |extra: &[&str]| -> Vec<String> { args.iter().chain(extra).map(|a| a.to_string()).collect() };
+
+        // checkpoints do not change the output
+        assert_eq!(analyze(&input, &arg(&["--checkpoint", ckpt, "--checkpoint-interval", "0"])), out);
+
+        // stop after depth 3 and resume (without the input)
+        let partial = analyze(&input, &arg(&["--checkpoint", ckpt, "--max-depth", "3"]));
+        assert!(partial.len() < out.len());
+        assert_eq!(analyze("", &arg(&["--checkpoint", ckpt, "--resume"])), out);
+
+        // resume again from the same checkpoint
+        assert_eq!(analyze("", &arg(&["--checkpoint", ckpt, "--resume"])), out);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
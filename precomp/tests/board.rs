@@ -0,0 +1,155 @@
+extern crate precomp;
+
+use precomp::board::{Board, Result, Status, CHICK, ELEPHANT, GIRAFFE, HEN, LION};
+use precomp::board_collection::BoardSet;
+
+// walk the first few plies from the initial board
+fn each_board<F>(depth: usize, mut f: F) where F: FnMut(Board) {
+    let mut visited = BoardSet::new();
+    let mut boards = vec![(Board::init().normalize(), 0)];
+    while let Some((b, d)) = boards.pop() {
+        if visited.contains(b) { continue }
+        visited.insert(b);
+        f(b);
+        if d == depth { continue }
+        if let Result::Unknown(bs) = b.next() {
+            for nb in bs { boards.push((nb, d + 1)) }
+        }
+    }
+}
+
+#[test]
+fn legal_moves_follow_next() {
+    each_board(6, |b| {
+        let ms = b.legal_moves();
+        match b.next() {
+            Result::Unknown(bs) => {
+                assert_eq!(ms.len(), bs.len());
+                for (m, nb) in ms.iter().zip(bs.iter()) {
+                    assert_eq!(b.apply(*m).reverse().normalize(), *nb);
+                }
+            }
+            Result::Win => assert!(ms.iter().any(|m| m.capture == LION.opponent())),
+            Result::Lose => assert!(ms.is_empty()),
+        }
+    });
+}
+
+#[test]
+fn initial_moves() {
+    let ms = Board::init().legal_moves();
+    assert_eq!(ms.len(), 4);
+    let m = ms[3];
+    assert_eq!(m.from, Some((1, 1)));
+    assert_eq!(m.to, (1, 2));
+    assert_eq!(m.piece, CHICK);
+    assert_eq!(m.capture, CHICK.opponent());
+    assert!(!m.promotion);
+    assert_eq!(Board::init().apply(m).hand(CHICK), 1);
+}
+
+#[test]
+fn each_next_follows_next() {
+    each_board(6, |b| {
+        let mut bs = vec![];
+        let s = b.each_next(|nb| { bs.push(nb); true });
+        match b.next() {
+            Result::Win => assert_eq!(s, Status::Win),
+            Result::Lose => assert_eq!(s, Status::Lose),
+            Result::Unknown(nbs) => {
+                assert_eq!(s, Status::Unknown);
+                assert_eq!(bs, nbs);
+            }
+        }
+        assert_eq!(b.status(), s);
+
+        // stop at the first board
+        let mut n = 0;
+        b.each_next(|_| { n += 1; false });
+        assert!(n <= 1);
+    });
+}
+
+#[test]
+fn bitboard_follows_naive() {
+    each_board(6, |b| {
+        assert_eq!(b.reverse(), b.reverse_naive());
+        assert_eq!(b.status(), b.status_naive());
+        let mut bs1 = vec![];
+        let mut bs2 = vec![];
+        assert_eq!(b.each_next(|nb| { bs1.push(nb); true }), b.each_next_naive(|nb| { bs2.push(nb); true }));
+        assert_eq!(bs1, bs2);
+        assert_eq!(b.prev(), b.prev_naive());
+    });
+}
+
+#[test]
+fn validate() {
+    each_board(6, |b| assert_eq!(b.validate(), Ok(()), "{:016x}", b.0));
+
+    // a chick dropped on the far rank
+    assert!(Board::init().del(1, 1).del(0, 3).put(0, 3, CHICK).validate().is_ok());
+
+    let b = Board::init();
+    assert!(Board(b.0 | 1 << 63).validate().is_err());          // unused bits
+    assert!(Board(b.0 | 7 << 4).validate().is_err());           // unknown piece
+    assert!(b.del(1, 0).validate().is_err());                   // no lion
+    assert!(b.put(0, 1, LION).validate().is_err());             // two lions
+    assert!(b.put(0, 1, CHICK).validate().is_err());            // three chicks
+    assert!(b.inc_hand(GIRAFFE).validate().is_err());           // three giraffes
+    assert!(Board(b.0 | 3 << 48).validate().is_err());          // an invalid hand counter
+    assert!(b.del(0, 0).inc_hand(GIRAFFE).validate().is_ok());
+}
+
+#[test]
+fn prev_of_chick_on_far_rank() {
+    // a board right after the move (seen from the player who moved), with a
+    // chick on the far rank
+    let b = Board(0).put(1, 0, LION).put(2, 3, LION.opponent()).put(0, 3, CHICK);
+    let bs = b.reverse().prev();
+
+    // the chick was dropped there
+    assert!(bs.contains(&b.del(0, 3).inc_hand(CHICK).normalize()));
+
+    // it cannot have moved there, because a chick that moves to the far rank
+    // is promoted (Board::prev returned this board before)
+    assert!(!bs.contains(&b.del(0, 3).put(0, 2, CHICK).normalize()));
+
+    // a hen on the far rank can be a promoted chick
+    let b = b.del(0, 3).put(0, 3, HEN);
+    assert!(b.reverse().prev().contains(&b.del(0, 3).put(0, 2, CHICK).normalize()));
+}
+
+#[test]
+fn prev_is_inverse_of_next() {
+    let mut all = BoardSet::new();
+    each_board(6, |b| { all.insert(b); });
+    each_board(5, |b| {
+        // all previous boards
+        if let Result::Unknown(bs) = b.next() {
+            for nb in bs { assert!(nb.prev().contains(&b)) }
+        }
+        // and nothing else (e.g., a chick on the far rank was dropped)
+        for pb in b.prev() {
+            if !all.contains(pb) { continue }
+            if let Result::Unknown(bs) = pb.next() { assert!(bs.contains(&b)) }
+        }
+    });
+}
+
+#[test]
+fn opponent_hands() {
+    // one elephant, two giraffes and no chick in the opponent's hand
+    let b = Board(1 << 54 | 2 << 56).put(1, 0, LION).put(1, 3, LION.opponent());
+    assert_eq!(b.hand(ELEPHANT.opponent()), 1);
+    assert_eq!(b.hand(GIRAFFE.opponent()), 2);
+    assert_eq!(b.hand(CHICK.opponent()), 0);
+    assert_eq!((b.hand(ELEPHANT), b.hand(GIRAFFE), b.hand(CHICK)), (0, 0, 0));
+
+    // the opponent's hands are the player's after reversing the board
+    let r = b.reverse();
+    for &p in [ELEPHANT, GIRAFFE, CHICK].iter() {
+        assert_eq!(r.hand(p), b.hand(p.opponent()));
+        assert_eq!(r.hand(p.opponent()), b.hand(p));
+    }
+}
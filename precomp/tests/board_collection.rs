@@ -0,0 +1,256 @@
+extern crate precomp;
+
+mod common;
+
+use precomp::board::Board;
+use precomp::board_collection::{self, BoardSet, BoardMap, SetImage, MapImage};
+
+// scattered keys (board-like, 60 bits)
+fn key(i: u64) -> Board {
+    Board(i.wrapping_mul(0x9e3779b97f4a7c15) >> 4)
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+struct Node {
+    depth: u8,
+    idx: u32,
+}
+
+#[test]
+fn small_values() {
+    let mut m: BoardMap<u8> = BoardMap::new();
+    for i in 0..100000 { m[key(i)] = (i % 251) as u8 }
+    assert_eq!(m.len(), 100000);
+    for i in 0..100000 { assert_eq!(m[key(i)], (i % 251) as u8) }
+
+    // the values survive resizing and an image round trip
+    let mut buf = vec![];
+    m.write_to(&mut buf).unwrap();
+    let m2: BoardMap<u8> = BoardMap::read_from(&mut &buf[..]).unwrap();
+    assert_eq!(m2.len(), m.len());
+    for i in 0..100000 { assert_eq!(m2[key(i)], m[key(i)]) }
+}
+
+#[test]
+fn struct_values() {
+    let mut m: BoardMap<Node> = BoardMap::new();
+    for i in 0..50000 {
+        m.put(key(i), Node { depth: (i % 100) as u8, idx: i as u32 });
+    }
+
+    // delete a half, and reinsert some of them: a reinserted board starts
+    // with the default value, not the one left in the slot
+    for i in (0..50000).filter(|i| i % 2 == 0) { m.delete(key(i)) }
+    assert_eq!(m.len(), 25000);
+    for i in (0..50000).filter(|i| i % 4 == 0) { m[key(i)].depth += 1 }
+    assert_eq!(m.len(), 37500);
+
+    for i in 0..50000 {
+        let b = key(i);
+        match i % 4 {
+            0 => assert_eq!(m[b], Node { depth: 1, idx: 0 }),
+            2 => assert!(!m.contains(b)),
+            _ => assert_eq!(m[b], Node { depth: (i % 100) as u8, idx: i as u32 }),
+        }
+    }
+}
+
+#[test]
+fn churn() {
+    // keep inserting and deleting so that the table is rehashed many times
+    let mut m: BoardMap<u16> = BoardMap::new();
+    for i in 0..200000u64 {
+        m[key(i)] = i as u16;
+        if i >= 1000 { m.delete(key(i - 1000)) }
+    }
+    assert_eq!(m.len(), 1000);
+    for i in 199000..200000 { assert_eq!(m[key(i)], i as u16) }
+    for i in 0..199000 { assert!(!m.contains(key(i))) }
+
+    // shrinking keeps the values
+    m.resize(m.len());
+    for i in 199000..200000 { assert_eq!(m[key(i)], i as u16) }
+}
+
+#[test]
+fn lookup() {
+    let mut m: BoardMap<u8> = BoardMap::new();
+
+    // an empty map has no board (not even in slot 0)
+    assert_eq!(m.get(key(0)), None);
+    assert_eq!(m.get_mut(key(0)), None);
+    assert_eq!(m.remove(key(0)), None);
+    assert_eq!(m.len(), 0);
+
+    for i in 0..1000 { *m.entry(key(i)).or_insert(1) += 1 }
+    for i in 0..1000 { *m.entry(key(i)).or_insert(1) += 1 }
+    m.entry(key(1000)).and_modify(|v| *v = 9).or_default();
+    assert_eq!(m.len(), 1001);
+    assert_eq!(m.get(key(0)), Some(&3));
+    assert_eq!(m.get(key(1000)), Some(&0));
+    assert_eq!(m.get(key(1001)), None);
+
+    *m.get_mut(key(5)).unwrap() = 7;
+    assert_eq!(m.remove(key(5)), Some(7));
+    assert_eq!(m.remove(key(5)), None);
+    assert_eq!(m.get(key(5)), None);
+    assert_eq!(m.len(), 1000);
+}
+
+#[test]
+#[should_panic(expected = "no such board")]
+fn index_absent() {
+    let mut m: BoardMap<u8> = BoardMap::new();
+    m[key(0)] = 1;
+    let _ = m[key(1)];
+}
+
+#[test]
+fn set_iterators() {
+    let mut s: BoardSet = (0..10000).map(key).collect();
+    s.extend((5000..15000).map(key));
+    assert_eq!(s.len(), 15000);
+
+    let mut bs: Vec<u64> = s.iter().map(|b| b.0).collect();
+    let mut expected: Vec<u64> = (0..15000).map(|i| key(i).0).collect();
+    bs.sort();
+    expected.sort();
+    assert_eq!(bs, expected);
+
+    // the bucket ranges cover all the boards
+    let n = s.buckets();
+    assert_eq!(s.iter_range(0..n / 3).count() + s.iter_range(n / 3..n).count(), 15000);
+    assert_eq!((&s).into_iter().count(), 15000);
+
+    s.retain(|b| b.0 % 2 == 0);
+    assert!(s.iter().all(|b| b.0 % 2 == 0));
+    assert_eq!(s.len(), s.iter().count());
+
+    let len = s.len();
+    let drained: Vec<Board> = s.drain().collect();
+    assert_eq!(drained.len(), len);
+    assert_eq!(s.len(), 0);
+    assert_eq!(s.iter().count(), 0);
+    assert_eq!(drained.into_iter().collect::<BoardSet>().into_iter().count(), len);
+}
+
+#[test]
+fn map_iterators() {
+    let mut m: BoardMap<Node> = (0..10000).map(|i| (key(i), Node { depth: 0, idx: i as u32 })).collect();
+    for (_, v) in m.iter_mut() { v.depth = (v.idx % 7) as u8 }
+    for (_, v) in &mut m { v.idx += 1 }
+    assert!(m.iter().all(|(b, v)| b == key(v.idx as u64 - 1) && v.depth as u32 == (v.idx - 1) % 7));
+    assert_eq!(m.keys().count(), 10000);
+    assert_eq!(m.values().map(|v| v.idx as u64).sum::<u64>(), 10000 * 10001 / 2);
+
+    m.retain(|_, v| v.depth != 0);
+    assert_eq!(m.len(), 10000 - 1429);
+    assert!(m.values().all(|v| v.depth != 0));
+
+    let mut entries: Vec<(Board, Node)> = m.drain().collect();
+    assert_eq!(entries.len(), 10000 - 1429);
+    assert_eq!(m.len(), 0);
+    entries.sort_by_key(|&(_, v)| v.idx);
+    assert_eq!(entries[0], (key(1), Node { depth: 1, idx: 2 }));
+    let m2: BoardMap<Node> = entries.into_iter().collect();
+    assert_eq!(m2.into_iter().count(), 10000 - 1429);
+}
+
+#[test]
+fn set_algebra() {
+    let a: BoardSet = (0..3000).map(key).collect();
+    let b: BoardSet = (2000..5000).map(key).collect();
+    let sorted = |s: BoardSet| { let mut v: Vec<u64> = s.iter().map(|b| b.0).collect(); v.sort(); v };
+    let expected = |r: std::ops::Range<u64>| { let mut v: Vec<u64> = r.map(|i| key(i).0).collect(); v.sort(); v };
+
+    assert_eq!(sorted(a.union(&b).collect()), expected(0..5000));
+    assert_eq!(a.union(&b).count(), 5000);
+    assert_eq!(sorted(a.intersection(&b).collect()), expected(2000..3000));
+    assert_eq!(sorted(b.intersection(&a).collect()), expected(2000..3000));
+    assert_eq!(sorted(a.difference(&b).collect()), expected(0..2000));
+    assert_eq!(sorted(b.difference(&a).collect()), expected(3000..5000));
+
+    let c: BoardSet = a.intersection(&b).collect();
+    assert!(c.is_subset(&a) && c.is_subset(&b));
+    assert!(!a.is_subset(&b));
+    assert!(BoardSet::new().is_subset(&a));
+}
+
+#[test]
+fn capacity() {
+    // no resizing while filling a reserved set
+    let mut s = BoardSet::with_capacity(10000);
+    let n = s.buckets();
+    for i in 0..10000 { s.insert(key(i)) ; }
+    assert_eq!(s.buckets(), n);
+
+    s.reserve(10000);
+    let n = s.buckets();
+    let bs: Vec<Board> = (10000..20000).map(key).collect();
+    s.insert_all(&bs);
+    assert_eq!(s.buckets(), n);
+    assert_eq!(s.len(), 20000);
+
+    // shrink after deleting most of the boards
+    for i in 0..19900 { s.delete(key(i)) }
+    s.shrink_to_fit();
+    assert_eq!(s.buckets(), 256);
+    assert_eq!(s.len(), 100);
+    assert!((19900..20000).all(|i| s.contains(key(i))));
+    s.shrink_to_fit();
+    assert_eq!(s.buckets(), 256);
+
+    let mut m: BoardMap<u8> = BoardMap::with_capacity(1000);
+    for i in 0..1000 { m[key(i)] = i as u8 }
+    for i in 10..1000 { m.delete(key(i)) }
+    m.shrink_to_fit();
+    assert!((0..10).all(|i| m[key(i)] == i as u8));
+}
+
+#[test]
+fn images() {
+    let dir = common::tmp_dir("images");
+    let (set_path, map_path) = (dir.join("set.img"), dir.join("map.img"));
+
+    let mut s: BoardSet = (0..20000).map(key).collect();
+    for i in 0..5000 { s.delete(key(i)) }
+    s.save(&set_path).unwrap();
+    let mut m: BoardMap<i8> = BoardMap::new();
+    for i in 0..20000 { m[key(i)] = (i % 100) as i8 - 1 }
+    m.save(&map_path).unwrap();
+
+    // a loaded table has the same layout (and thus the same order)
+    let s2 = BoardSet::load(&set_path).unwrap();
+    assert_eq!(s2.iter().collect::<Vec<_>>(), s.iter().collect::<Vec<_>>());
+    let m2: BoardMap<i8> = BoardMap::load(&map_path).unwrap();
+    assert_eq!(m2.iter().map(|(b, &v)| (b, v)).collect::<Vec<_>>(), m.iter().map(|(b, &v)| (b, v)).collect::<Vec<_>>());
+
+    // mapped images
+    assert!(board_collection::is_image(&set_path));
+    let si = SetImage::open(&set_path).unwrap();
+    assert_eq!(si.len(), 15000);
+    assert!((0..20000).all(|i| si.contains(key(i)) == (i >= 5000)));
+    assert_eq!(si.iter().collect::<Vec<_>>(), s.iter().collect::<Vec<_>>());
+    let mi: MapImage<i8> = MapImage::open(&map_path).unwrap();
+    assert_eq!(mi.len(), 20000);
+    assert!((0..20000).all(|i| mi.get(key(i)) == Some((i % 100) as i8 - 1)));
+    assert_eq!(mi.get(key(20000)), None);
+    assert_eq!(mi.iter().collect::<Vec<_>>(), m.iter().map(|(b, &v)| (b, v)).collect::<Vec<_>>());
+
+    // wrong kinds of tables, and broken images
+    assert!(MapImage::<i8>::open(&set_path).is_err());
+    assert!(MapImage::<u16>::open(&map_path).is_err());
+    assert!(SetImage::open(&map_path).is_err());
+    let data = std::fs::read(&map_path).unwrap();
+    std::fs::write(&map_path, &data[..data.len() - 1]).unwrap();
+    assert!(MapImage::<i8>::open(&map_path).is_err());
+    assert!(BoardMap::<i8>::load(&map_path).is_err());
+
+    // an empty set
+    BoardSet::new().save(&set_path).unwrap();
+    let si = SetImage::open(&set_path).unwrap();
+    assert_eq!(si.len(), 0);
+    assert!(!si.contains(key(0)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
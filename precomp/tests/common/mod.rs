@@ -0,0 +1,55 @@
+// fixtures shared by the integration tests (`mod common;`)
+#![allow(dead_code)]
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use precomp::board::{Board, Status, CHICK, LION};
+use precomp::board_collection::BoardSet;
+
+// a new temporary directory (the caller removes it)
+pub fn tmp_dir(name: &str) -> PathBuf {
+    static N: AtomicUsize = AtomicUsize::new(0);
+    let n = N.fetch_add(1, Ordering::SeqCst);
+    let dir = env::temp_dir().join(format!("precomp-{}-{}-{}", name, process::id(), n));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// all boards reachable from a board with two lions and a chick, and the same
+// boards in the format of 1-enum's output
+pub fn enumerate() -> (Vec<Board>, String) {
+    let b = Board(0).put(1, 0, LION).put(1, 3, LION.opponent()).put(1, 1, CHICK);
+    let mut boards = vec![];
+    let mut s = String::new();
+    let mut stack = vec![b.normalize()];
+    let mut visited = BoardSet::new();
+    while let Some(b) = stack.pop() {
+        if visited.contains(b) { continue }
+        visited.insert(b);
+        let depth = match b.each_next(|nb| { stack.push(nb); true }) {
+            Status::Win => 1,
+            Status::Lose => 0,
+            Status::Unknown => -1,
+        };
+        boards.push(b);
+        s += &format!("{:015x} {}\n", b.0, depth);
+    }
+    (boards, s)
+}
+
+// run 2-analyze
+pub fn analyze<S: AsRef<OsStr>>(input: &str, args: &[S]) -> Vec<u8> {
+    let dir = tmp_dir("analyze");
+    let path = dir.join("1.txt");
+    File::create(&path).unwrap().write_all(input.as_bytes()).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_2-analyze")).args(args)
+        .stdin(File::open(&path).unwrap()).stderr(Stdio::null()).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(out.status.success());
+    out.stdout
+}
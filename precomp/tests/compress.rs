@@ -0,0 +1,33 @@
+extern crate precomp;
+
+use precomp::compress;
+use precomp::phf::Phf;
+
+// a table whose slots are mostly empty (0), like the real data base
+fn table(n: usize) -> Vec<u32> {
+    let mut x = 12345u64;
+    (0..n).map(|i| {
+        x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let r = (x >> 33) as u32;
+        if i == 0 || r % 5 == 0 { 0 } else { r % (34 * 37) * 3 + r % 3 }
+    }).collect()
+}
+
+#[test]
+fn round_trip() {
+    for &n in [1, 10, 1000, 30000].iter() {
+        let phf = Phf { ps: [101, 103, 107], table: table(n) };
+        let s = compress::encode(&phf);
+        assert!(s.bytes().all(|c| (32..=126).contains(&c) && c != b'"' && c != b'\\'));
+
+        let phf2 = compress::decode(&s).unwrap();
+        assert_eq!(phf2.ps, phf.ps);
+        assert_eq!(phf2.table, phf.table);
+    }
+}
+
+#[test]
+fn invalid() {
+    assert!(compress::decode("").is_none());
+    assert!(compress::decode("abc").is_none());
+}
@@ -0,0 +1,180 @@
+extern crate precomp;
+
+mod common;
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::process::{Command, Output};
+use std::time::{Duration, SystemTime};
+use precomp::board::{self, Board};
+use precomp::board_collection::{BoardMap, BoardSet};
+use precomp::compress;
+use precomp::phf::{self, Phf};
+use common::{enumerate, tmp_dir};
+
+fn dobutsu(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dobutsu")).args(args).output().unwrap()
+}
+
+#[test]
+fn analyze_and_probe() {
+    let dir = tmp_dir("probe");
+    let (p1, p2) = (dir.join("1.txt"), dir.join("2.txt"));
+    fs::write(&p1, enumerate().1).unwrap();
+
+    let out = dobutsu(&["analyze", "-i", p1.to_str().unwrap(), "-o", p2.to_str().unwrap(), "--format", "binary", "--quiet"]);
+    assert!(out.status.success());
+    assert!(out.stderr.is_empty());
+    assert!(fs::read(&p2).unwrap().starts_with(precomp::MAGIC));
+
+    let out = dobutsu(&["probe", "-i", p2.to_str().unwrap(), "--quiet", "1l1/1c1/3/1L1 b -"]);
+    assert!(out.status.success());
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert!(s.starts_with("1l1/1c1/3/1L1 b -\n  value: "));
+    assert!(s.lines().any(|l| l.starts_with("  * ")));
+
+    // the same answer from an image
+    let p3 = dir.join("2.img");
+    let out = dobutsu(&["image", "-i", p2.to_str().unwrap(), "-o", p3.to_str().unwrap(), "--quiet"]);
+    assert!(out.status.success());
+    assert!(fs::read(&p3).unwrap().starts_with(precomp::board_collection::IMAGE_MAGIC));
+    let out = dobutsu(&["probe", "-i", p3.to_str().unwrap(), "--quiet", "1l1/1c1/3/1L1 b -"]);
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), s);
+
+    // a missing input
+    let out = dobutsu(&["analyze", "-i", dir.join("none.txt").to_str().unwrap(), "-o", p2.to_str().unwrap()]);
+    assert!(!out.status.success());
+
+    // the logs of a failed stage are shown even with --quiet
+    fs::write(&p1, "zzz 0\n").unwrap();
+    let out = dobutsu(&["analyze", "-i", p1.to_str().unwrap(), "-o", p2.to_str().unwrap(), "--quiet"]);
+    assert!(!out.status.success());
+    let log = String::from_utf8(out.stderr).unwrap();
+    assert!(log.contains("line 1: invalid board"), "{}", log);
+    assert!(log.contains("2-analyze failed"), "{}", log);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// a fake 2-analyze's output that has just the boards 3-extract visits: the
+// next boards of a black board are one ply shallower, and a white board has
+// one best move (down to depth 3)
+fn analyzed() -> String {
+    let mut oracle: BoardMap<i8> = BoardMap::new();
+    let mut s = String::new();
+    let mut queue = VecDeque::new();
+    queue.push_back((Board::init().normalize(), 10));
+    while let Some((b, depth)) = queue.pop_front() {
+        if oracle.contains(b) { continue }
+        oracle[b] = depth;
+        s += &format!("{:015x} {}\n", b.0, depth);
+        if let board::Result::Unknown(bs) = b.next() {
+            if depth % 2 == 0 {
+                queue.extend(bs.into_iter().map(|nb| (nb, depth - 1)));
+            } else if depth > 3 {
+                queue.push_back((bs[0], depth - 1));
+            }
+        }
+    }
+    s
+}
+
+#[test]
+fn extract_from_image() {
+    let dir = tmp_dir("extract");
+    let (p2, p3) = (dir.join("2.txt"), dir.join("2.img"));
+    fs::write(&p2, analyzed()).unwrap();
+
+    let out = dobutsu(&["extract", "-i", p2.to_str().unwrap(), "-o", dir.join("3.txt").to_str().unwrap(), "--quiet"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let s = fs::read(dir.join("3.txt")).unwrap();
+    assert!(s.starts_with(b" 3 2 1 0\n400a00390c1b002 9 0\n"));
+
+    // the same 3.txt from an image
+    let out = dobutsu(&["image", "-i", p2.to_str().unwrap(), "-o", p3.to_str().unwrap(), "--quiet"]);
+    assert!(out.status.success());
+    let out = dobutsu(&["extract", "-i", p3.to_str().unwrap(), "-o", dir.join("3.img.txt").to_str().unwrap(), "--quiet"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(fs::read(dir.join("3.img.txt")).unwrap(), s);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn pipeline_skips_up_to_date_stages() {
+    let dir = tmp_dir("pipeline");
+
+    // a small data base (the boards of the first few plies)
+    let mut boards = vec![];
+    let mut visited = BoardSet::new();
+    let mut stack = vec![(Board::init().normalize(), 0)];
+    while let Some((b, ply)) = stack.pop() {
+        if ply == 4 || visited.contains(b) { continue }
+        visited.insert(b);
+        boards.push((b, phf::pack(5, boards.len() as i32 % 34)));
+        b.each_next(|nb| { stack.push((nb, ply + 1)); true });
+    }
+    let phf = phf::build(&boards).unwrap();
+
+    // all files are up to date except ai.txt (older than 5.txt)
+    let now = SystemTime::now();
+    let files = ["ai.txt", "1.txt", "2.txt", "3.txt", "4.lp", "4.lp.map", "4.sol", "4.txt", "5.txt", "9.txt"];
+    for (i, name) in files.iter().enumerate() {
+        let path = dir.join(name);
+        fs::write(&path, if *name == "5.txt" { format!("{}\n", phf) } else { String::new() }).unwrap();
+        File::options().write(true).open(&path).unwrap().set_modified(now + Duration::from_secs(i as u64 + 10)).unwrap();
+    }
+
+    let out = dobutsu(&["pipeline", "-d", dir.to_str().unwrap()]);
+    let log = String::from_utf8(out.stderr).unwrap();
+    assert!(out.status.success(), "{}", log);
+    assert!(log.contains("1.txt: up to date"));
+    assert!(log.contains("4.lp, "));
+    assert!(log.contains("ai.txt: running 6-compress"));
+    assert!(log.contains("9.txt: up to date"));
+
+    let ai = fs::read_to_string(dir.join("ai.txt")).unwrap();
+    let phf2: Phf = compress::decode(&ai).unwrap();
+    assert_eq!(phf2.table, phf.table);
+
+    // 4.sol must be newer than 4.lp
+    File::options().write(true).open(dir.join("4.sol")).unwrap().set_modified(now).unwrap();
+    let out = dobutsu(&["pipeline", "-d", dir.to_str().unwrap(), "--quiet"]);
+    assert!(!out.status.success());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn pipeline_renames_outputs_of_4_opt() {
+    let dir = tmp_dir("lp");
+
+    // 3.txt (an empty DAG) is newer than 4.lp
+    let now = SystemTime::now();
+    for (i, name) in ["4.lp", "1.txt", "2.txt", "3.txt"].iter().enumerate() {
+        let path = dir.join(name);
+        fs::write(&path, "").unwrap();
+        File::options().write(true).open(&path).unwrap().set_modified(now + Duration::from_secs(i as u64 + 10)).unwrap();
+    }
+
+    // 4-opt fails writing 4.lp.map (after writing 4.lp): 4.lp is kept
+    fs::create_dir(dir.join("4.tmp.lp.map")).unwrap();
+    let out = dobutsu(&["pipeline", "-d", dir.to_str().unwrap()]);
+    let log = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(log.contains("4-opt failed"), "{}", log);
+    assert_eq!(fs::read(dir.join("4.lp")).unwrap(), b"");
+    assert!(!dir.join("4.tmp.lp").exists());
+    assert!(!dir.join("4.lp.map").exists());
+
+    // 4.lp and 4.lp.map are renamed (and then 4.sol is missing)
+    fs::remove_dir(dir.join("4.tmp.lp.map")).unwrap();
+    let out = dobutsu(&["pipeline", "-d", dir.to_str().unwrap(), "--quiet"]);
+    assert!(!out.status.success());
+    assert!(fs::read_to_string(dir.join("4.lp")).unwrap().starts_with("minimize\n"));
+    assert!(dir.join("4.lp.map").exists());
+    assert!(!dir.join("4.tmp.lp").exists() && !dir.join("4.tmp.lp.map").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
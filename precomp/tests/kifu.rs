@@ -0,0 +1,57 @@
+extern crate precomp;
+
+use precomp::board::{Board, Color, CHICK, HEN};
+use precomp::kifu::{self, Kifu, Style};
+
+const GAME: &str = "\
+▲B2ひよこ
+△B2ライオン
+▲B3ぞう
+△C2ひよこ
+▲C2ぞう
+△C2ライオン
+";
+
+#[test]
+fn read_and_write() {
+    let kifu = Kifu::read(GAME).unwrap();
+    assert_eq!(kifu.len(), 6);
+    assert_eq!(kifu.write(Style::Japanese).unwrap(), GAME);
+
+    let ascii = kifu.write(Style::Ascii).unwrap();
+    assert_eq!(ascii, "B3B2\nB1B2\nA4B3\nC*C2\nB3C2\nB2C2\n");
+    assert_eq!(Kifu::read(&ascii).unwrap(), kifu);
+    assert_eq!(Kifu::read("B2ひよこ B1B2").unwrap().len(), 2);
+    assert_eq!(kifu.replay().unwrap().len(), 7);
+}
+
+fn write(b1: Board, b2: Board) -> String {
+    let m = b1.legal_moves().into_iter().find(|&m| b1.apply(m) == b2).unwrap();
+    kifu::write_move(b1, Color::Black, m, Style::Japanese)
+}
+
+#[test]
+fn suffixes() {
+    let b1 = Board(0).put(0, 0, HEN).put(2, 0, HEN);
+    let b2 = Board(0).put(0, 0, HEN).put(1, 0, HEN);
+    assert_eq!(write(b1, b2), "▲B4にわとり右");
+
+    let b1 = Board(0).put(0, 0, CHICK).inc_hand(CHICK);
+    let b2 = Board(0).put(0, 0, CHICK).put(0, 1, CHICK);
+    assert_eq!(write(b1, b2), "▲C3ひよこ打");
+
+    let b1 = Board(0).put(0, 2, CHICK);
+    let b2 = Board(0).put(0, 3, HEN);
+    assert_eq!(write(b1, b2), "▲C1ひよこ成");
+}
+
+#[test]
+fn illegal_move() {
+    let e = Kifu::read("▲B2ひよこ\n△B3ライオン\n").unwrap_err();
+    assert_eq!(e.ply, 2);
+    let e = Kifu::read("# comment\nB3B2 B1B2 B2B1").unwrap_err();
+    assert_eq!(e.ply, 3);
+    let e = Kifu::read("B3B2 B1B2 B4B3 B2B3 A4A3").unwrap_err();
+    assert_eq!(e.msg, "the game is already over");
+    assert_eq!(e.ply, 5);
+}
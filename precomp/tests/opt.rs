@@ -0,0 +1,191 @@
+extern crate precomp;
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use precomp::{Format, In, Nodes, Records};
+use precomp::board::Board;
+use common::tmp_dir;
+
+// a small DAG (3-extract's output) with the features that 4-opt golfs:
+//   - the start nodes (0, 1) and the nodes forced by them are pinned
+//   - 8 -> {11} -> 11 is a chain that is merged into 8
+//   - the move of 10 leads to a board that is not in the DAG (13)
+//   - 12 is not reachable from the start nodes (discarded)
+const DAG : &'static str = r#" 0 1
+0000000000000a0 5 0
+ 0: 2
+
+0000000000000a1 5 1
+ 1: 3 4
+ 2: 5
+
+0000000000000a2 7 2
+ 3: 6
+ 4: 7
+
+0000000000000a3 7 3
+ 0: 6 7
+
+0000000000000a4 7 4
+ 2: 8
+
+0000000000000a5 9 5
+ 1: 8
+ 5: 9
+
+0000000000000a6 9 6
+ 0: 10
+
+0000000000000a7 11 7
+ 0: 10
+
+0000000000000a8 11 8
+ 3: 11
+
+0000000000000a9 13 9
+ 1: 6
+
+0000000000000aa 13 10
+ 0: 13
+
+0000000000000ab 9 11
+ 0: 9
+
+0000000000000ac 9 12
+ 0: 9
+
+"#;
+
+// the expected outputs, traced through 4-opt.rb
+const LP : &'static str = r#"minimize
+F
++ B
++ C
++ J
++ 2 A
++ H
++ G
++ D
+
+subject to
+A: K + F >= 1
+B: A + B - F >= 0
+C: I - B >= 0
+D: C - I >= 0
+F: J - C >= 0
+G: B - A >= 0
+H: G + H - 2 K >= 0
+I: A - H >= 0
+J: C + D - 2 G >= 0
+K: J - D >= 0
+L: I + L >= 1
+M: D - L >= 0
+
+binary
+F
+B
+I
+C
+J
+A
+K
+H
+G
+D
+L
+
+end
+"#;
+
+const LP_MAP : &'static str = r#"0000000000000a0 (pinned)
+0000000000000a1 (pinned)
+0000000000000a2 (pinned)
+0000000000000a3 G
+0000000000000a4 H
+0000000000000a5 F
+0000000000000a6 C
+0000000000000a7 D
+0000000000000a8 A
+0000000000000a9 B
+0000000000000aa J
+0000000000000ab A
+0000000000000ac (discarded)
+"#;
+
+// an optimal solution in the format of SCIP
+const SOL : &'static str = "solution status: optimal solution found\n\
+objective value:                                4\n\
+F                                                   1 \t(obj:1)\n\
+B                                                   1 \t(obj:1)\n\
+I                                                   1 \t(obj:0)\n\
+C                                                   1 \t(obj:1)\n\
+J                                                   1 \t(obj:1)\n\
+";
+
+const TXT : &'static str = r#"0000000000000a0 5 0
+0000000000000a1 5 2
+0000000000000a2 7 3
+0000000000000a5 9 5
+0000000000000a6 9 0
+0000000000000a9 13 1
+0000000000000aa 13 0
+"#;
+
+fn opt(args: &[&Path]) -> Vec<u8> {
+    let out = Command::new(env!("CARGO_BIN_EXE_4-opt")).args(args).output().unwrap();
+    assert!(out.status.success());
+    out.stdout
+}
+
+#[test]
+fn lp() {
+    let dir = tmp_dir("lp");
+    let (p3, lp) = (dir.join("3.txt"), dir.join("4.lp"));
+    fs::write(&p3, DAG).unwrap();
+    opt(&[&p3, &lp]);
+    assert_eq!(fs::read_to_string(&lp).unwrap(), LP);
+    assert_eq!(fs::read_to_string(dir.join("4.lp.map")).unwrap(), LP_MAP);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invert_solution() {
+    let dir = tmp_dir("sol");
+    let (p3, sol, map) = (dir.join("3.txt"), dir.join("4.sol"), dir.join("4.lp.map"));
+    fs::write(&p3, DAG).unwrap();
+    fs::write(&sol, SOL).unwrap();
+    fs::write(&map, LP_MAP).unwrap();
+
+    // the pinned boards are kept, the discarded board is not, and the
+    // merged board (11) follows the variable of the node it is merged into (8)
+    assert_eq!(String::from_utf8(opt(&[&p3, &sol, &map])).unwrap(), TXT);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn binary_formats() {
+    let dir = tmp_dir("binary");
+    let (p3, lp, sol, map) = (dir.join("3.txt"), dir.join("4.lp"), dir.join("4.sol"), dir.join("4.lp.map"));
+
+    // the same DAG in the binary format
+    let nodes = Nodes::new(DAG.as_bytes()).unwrap();
+    let mut buf = vec![];
+    Format::Binary.write_start(&mut buf, 13, nodes.start()).unwrap();
+    for node in nodes { Format::Binary.write_node(&mut buf, &node.unwrap()).unwrap() }
+    fs::write(&p3, buf).unwrap();
+
+    opt(&[&p3, &lp]);
+    assert_eq!(fs::read_to_string(&lp).unwrap(), LP);
+    assert_eq!(fs::read_to_string(&map).unwrap(), LP_MAP);
+
+    // binary records with move indexes
+    fs::write(&sol, SOL).unwrap();
+    let out = opt(&[Path::new("--format"), Path::new("binary"), &p3, &sol, &map]);
+    let records: Vec<(Board, i32, i32)> = Records::new(&out[..], false).unwrap().map(|r| r.unwrap()).collect();
+    let expected: Vec<(Board, i32, i32)> = TXT.lines().map(|l| In::parse(l, false).unwrap()).collect();
+    assert_eq!(records, expected);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,122 @@
+extern crate precomp;
+
+use precomp::board::{Board, Result, Status, LION};
+use precomp::board_collection::BoardSet;
+use precomp::compress;
+use precomp::oracle::Oracle;
+use precomp::phf;
+
+// ai.txt is built by make (the same file as the client tests use)
+fn load() -> Oracle {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/ai.txt");
+    Oracle::load(path).unwrap_or_else(|e| panic!("{}: {} (run make first)", path, e))
+}
+
+// a small data base: the deep boards of the first few plies with made-up
+// depths and move indexes
+fn small_oracle() -> (Oracle, Vec<(Board, i32, usize)>) {
+    let mut visited = BoardSet::new();
+    let mut entries = vec![];
+    let mut queue = vec![Board::init().normalize()];
+    for _ in 0..4 {
+        let mut next = vec![];
+        for b in queue {
+            if let Result::Unknown(bs) = b.next() {
+                for nb in bs {
+                    if visited.contains(nb) { continue }
+                    visited.insert(nb);
+                    next.push(nb);
+                    let nb = nb.normalize();
+                    if nb.status() != Status::Unknown || nb.easy_next().is_some() { continue }
+                    let n = entries.len();
+                    entries.push((nb, n as i32 % 30 * 2 + 5, n % nb.legal_moves().len()));
+                }
+            }
+        }
+        queue = next;
+    }
+    let boards: Vec<(Board, u32)> = entries.iter().map(|&(b, depth, idx)| (b, phf::pack(depth, idx as i32))).collect();
+    let ai = compress::encode(&phf::build(&boards).unwrap());
+    (Oracle::new(&ai).unwrap(), entries)
+}
+
+fn has_lion(b: Board) -> bool {
+    (0..3).any(|x| (0..4).any(|y| b.get(x, y) == LION))
+}
+
+#[test]
+fn lookup() {
+    let (oracle, entries) = small_oracle();
+    assert!(entries.len() > 100);
+    for (b, depth, idx) in entries {
+        assert_eq!(oracle.lookup(b), (depth, idx));
+        let nb = b.apply(b.legal_moves()[idx]);
+        assert_eq!(oracle.next(b), Some((depth, nb)));
+
+        // the normalization is inverted
+        assert_eq!(oracle.next(b.flip()), Some((depth, nb.flip())));
+        assert_eq!(oracle.search(b.reverse()), Some((depth, nb.reverse())));
+    }
+}
+
+#[test]
+fn shallow_boards() {
+    let (oracle, _) = small_oracle();
+
+    // the opponent's lion can be captured
+    let b = Board(0).put(1, 1, LION).put(1, 2, LION.opponent());
+    assert_eq!(b.status(), Status::Win);
+    let (depth, nb) = oracle.next(b).unwrap();
+    assert_eq!(depth, 1);
+    assert!(!has_lion(nb.reverse()));
+    assert_eq!(nb.get(1, 2), LION);
+
+    // the lion can try (mirrored, so that the board is flipped by the
+    // normalization)
+    for &x in &[0, 2] {
+        let b = Board(0).put(x, 2, LION).put(2 - x, 3, LION.opponent());
+        assert_eq!(b.status(), Status::Unknown);
+        assert_eq!(b.easy_next().map(|(depth, _)| depth), Some(1));
+        let (depth, nb) = oracle.next(b).unwrap();
+        assert_eq!(depth, 1);
+        assert!((0..3).any(|x| nb.get(x, 3) == LION));
+        assert_eq!(nb.get(2 - x, 3), LION.opponent());
+    }
+
+    // the game is over
+    let b = Board(0).put(0, 3, LION).put(2, 0, LION.opponent());
+    assert_eq!(b.status(), Status::Lose);
+    assert_eq!(oracle.next(b), None);
+}
+
+#[test]
+#[ignore = "needs ai.txt (built by make)"]
+fn win_against_random_player() {
+    let oracle = load();
+    let mut x = 1u64;
+    for _ in 0..100 {
+        let mut b = Board::init(); // seen from black
+        let mut last = None;
+        loop {
+            // black plays randomly
+            let ms = b.legal_moves();
+            assert_eq!(b.status(), Status::Unknown);
+            x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let m = ms[(x >> 33) as usize % ms.len()];
+
+            // white follows the oracle
+            let (depth, nb) = oracle.next(b.apply(m).reverse()).unwrap();
+            assert_eq!(depth % 2, 1);
+            if let Some(d) = last { assert!(depth < d) }
+            last = Some(depth);
+
+            let b2 = nb.reverse();
+            assert_eq!(oracle.search(b.apply(m)), Some((depth, b2)));
+            b = b2;
+            if depth == 1 {
+                assert!(!has_lion(b) || b.status() == Status::Lose);
+                break
+            }
+        }
+    }
+}
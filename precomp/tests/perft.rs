@@ -0,0 +1,29 @@
+extern crate precomp;
+
+use precomp::board::{Board, LION};
+use precomp::perft;
+
+#[test]
+fn initial_board() {
+    let counts = [1, 4, 17, 96, 577, 3199, 18754];
+    for (d, &n) in counts.iter().enumerate() {
+        assert_eq!(perft::perft(Board::init(), d), n);
+    }
+    let counts = [1, 4, 17, 83, 462, 2135, 8867];
+    for (d, &n) in counts.iter().enumerate() {
+        assert_eq!(perft::perft_merged(Board::init(), d), n);
+    }
+}
+
+#[test]
+fn divide() {
+    let r = perft::divide(Board::init(), 3, false);
+    assert_eq!(r.iter().map(|&(_, n)| n).collect::<Vec<_>>(), vec![17, 16, 19, 44]);
+    assert_eq!(r.iter().map(|&(m, _)| m).collect::<Vec<_>>(), Board::init().legal_moves());
+    assert!(perft::divide(Board::init(), 0, false).is_empty());
+
+    // the opponent's lion can be captured: legal moves, but no next board
+    let b = Board(0).put(1, 0, LION).put(1, 1, LION.opponent());
+    assert!(!b.legal_moves().is_empty());
+    assert!(perft::divide(b, 2, false).is_empty());
+}
@@ -0,0 +1,86 @@
+extern crate precomp;
+
+use precomp::board::{Board, Result};
+use precomp::board_collection::BoardSet;
+use precomp::Format;
+use precomp::phf::{self, Phf};
+
+// the boards of the first few plies with dummy depths and move indexes
+fn boards() -> Vec<(Board, u32)> {
+    let mut visited = BoardSet::new();
+    let mut boards = vec![];
+    let mut queue = vec![Board::init().normalize()];
+    for _ in 0..4 {
+        let mut next = vec![];
+        for b in queue {
+            if let Result::Unknown(bs) = b.next() {
+                for nb in bs {
+                    if visited.contains(nb) { continue }
+                    visited.insert(nb);
+                    let n = boards.len() as i32;
+                    boards.push((nb, phf::pack(n % 37 * 2 + 5, n % 34)));
+                    next.push(nb);
+                }
+            }
+        }
+        queue = next;
+    }
+    boards
+}
+
+#[test]
+fn pack() {
+    assert_eq!(phf::pack(5, 0), 0);
+    assert_eq!(phf::pack(7, 3), 37);
+    assert_eq!(phf::unpack(37), (7, 3));
+    assert_eq!(phf::unpack(phf::pack(77, 33)), (77, 33));
+}
+
+#[test]
+fn build() {
+    let boards = boards();
+    let phf = phf::build(&boards).unwrap();
+    assert_eq!(phf.table.len() as u64, phf.ps.iter().sum());
+    assert!(phf.load_factor(boards.len()) > 0.5);
+    for &(b, v) in &boards {
+        assert_eq!(phf.get(b), v);
+    }
+
+    let phf2 = Phf::parse(&phf.to_string()).unwrap();
+    assert_eq!(phf2.ps, phf.ps);
+    assert_eq!(phf2.table, phf.table);
+}
+
+#[test]
+fn same_seed_as_ruby() {
+    // 5-phf.rb picks [263, 269, 271] for these boards (a search with other
+    // midpoints may settle on [223, 227, 229], which also works); the digest
+    // is of the table that 5-phf.rb builds
+    let phf = phf::build(&boards()).unwrap();
+    assert_eq!(phf.ps, [263, 269, 271]);
+    assert_eq!(phf.table.len(), 803);
+    let digest: u64 = phf.table.iter().enumerate().map(|(i, &v)| v as u64 * (i as u64 + 1)).sum();
+    assert_eq!(digest, 370539990);
+}
+
+#[test]
+fn formats() {
+    let phf = phf::build(&boards()).unwrap();
+    for &format in [Format::Text, Format::Binary].iter() {
+        let mut buf = vec![];
+        phf.write(&mut buf, format).unwrap();
+        let phf2 = Phf::read(&buf[..]).unwrap();
+        assert_eq!(phf2.ps, phf.ps);
+        assert_eq!(phf2.table, phf.table);
+        assert!(Phf::read(&buf[..buf.len() - 2]).is_err());
+    }
+
+    let mut buf = vec![];
+    phf.write(&mut buf, Format::Text).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), format!("{}\n", phf));
+    let mut buf = vec![];
+    phf.write(&mut buf, Format::Binary).unwrap();
+    assert_eq!(buf.len(), precomp::HEADER_SIZE + 3 * 8 + phf.table.len() * 4);
+    buf.push(0);
+    assert!(Phf::read(&buf[..]).is_err());
+}
@@ -0,0 +1,76 @@
+extern crate precomp;
+
+use precomp::board::{Board, Color, Result};
+use precomp::board_collection::BoardMap;
+use precomp::position::{Cycle, Outcome, Position, RepetitionRule};
+
+#[test]
+fn make_and_unmake() {
+    let mut pos = Position::init();
+    let ms = pos.legal_moves();
+    pos.make(ms[3]);
+    assert_eq!(pos.side(), Color::White);
+    assert_eq!(pos.ply(), 1);
+    assert_eq!(pos.to_string(), "gle/1C1/3/ELG w C");
+    assert_eq!(pos.absolute(), pos.board().reverse());
+
+    assert_eq!(pos.unmake(), Some(ms[3]));
+    assert_eq!(pos, Position::init());
+    assert_eq!(pos.unmake(), None);
+}
+
+#[test]
+fn make_next() {
+    let mut pos = Position::init();
+    let mut mirrored = 0;
+    if let Result::Unknown(bs) = pos.canonical().next() {
+        for nb in bs {
+            let m = pos.make_next(nb).unwrap();
+            assert_eq!(pos.canonical(), nb);
+            assert_eq!(pos.absolute(), Board::init().apply(m));
+            if pos.mirrored() { mirrored += 1 }
+            pos.unmake();
+        }
+    }
+    assert!(mirrored > 0);
+    assert_eq!(pos.make_next(Board::init()), None);
+}
+
+// move the giraffes back and forth
+const SHUFFLE: [((i8, i8), (i8, i8)); 4] = [((0, 0), (0, 1)), ((2, 0), (2, 1)), ((0, 1), (0, 0)), ((2, 1), (2, 0))];
+
+fn shuffle_one(pos: &mut Position, i: usize) {
+    let (from, to) = SHUFFLE[i];
+    let m = pos.legal_moves().into_iter().find(|m| m.from == Some(from) && m.to == to).unwrap();
+    pos.make(m);
+}
+
+fn shuffle(pos: &mut Position) {
+    for i in 0..4 { shuffle_one(pos, i) }
+}
+
+#[test]
+fn repetition() {
+    let rule = RepetitionRule::default();
+    let mut pos = Position::init();
+    shuffle(&mut pos);
+    assert_eq!(pos.repetitions(), 2);
+    assert_eq!(pos.outcome(rule), None);
+    shuffle(&mut pos);
+    assert_eq!(pos.repetitions(), 3);
+    assert_eq!(pos.outcome(rule), Some(Outcome::Draw));
+    assert_eq!(pos.outcome(RepetitionRule { times: 4 }), None);
+
+    // follow the same line from the canonical boards
+    let mut pos = Position::init();
+    let mut line = BoardMap::new();
+    let mut boards = vec![];
+    for i in 0..4 {
+        line[pos.canonical()] = i;
+        boards.push(pos.canonical());
+        shuffle_one(&mut pos, i as usize);
+    }
+    let cycle = Position::init().find_cycle(|b| Some(boards[(line[b] as usize + 1) % 4]));
+    assert_eq!(cycle, Some(Cycle { start: 0, len: 4 }));
+    assert_eq!(Position::init().find_cycle(|_| None), None);
+}
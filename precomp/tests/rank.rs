@@ -0,0 +1,47 @@
+extern crate precomp;
+
+use precomp::board::{Board, Result};
+use precomp::board_collection::BoardSet;
+use precomp::rank::{self, N};
+
+#[test]
+fn reachable_boards() {
+    let mut visited = BoardSet::new();
+    let mut ranks = BoardSet::new();
+    let mut boards = vec![(Board::init().normalize(), 0)];
+    while let Some((b, d)) = boards.pop() {
+        if visited.contains(b) { continue }
+        visited.insert(b);
+
+        let r = rank::rank(b).unwrap();
+        assert!(r < N);
+        assert_eq!(rank::unrank(r), Some(b));
+        assert_eq!(rank::rank(b.flip()), Some(r));
+        ranks.insert(Board(r));
+
+        if d == 6 { continue }
+        if let Result::Unknown(bs) = b.next() {
+            for nb in bs { boards.push((nb, d + 1)) }
+        }
+    }
+    assert_eq!(ranks.len(), visited.len());
+}
+
+#[test]
+fn all_ranks() {
+    let mut r = 0;
+    while r < N {
+        let b = rank::unrank(r).unwrap();
+        let r2 = rank::rank(b).unwrap();
+        assert_eq!(rank::unrank(r2), Some(b));
+        r += 9973;
+    }
+    assert_eq!(rank::unrank(N), None);
+}
+
+#[test]
+fn invalid_boards() {
+    assert_eq!(rank::rank(Board(0)), None);
+    assert_eq!(rank::rank(Board::init().del(0, 0)), None);
+    assert_eq!(rank::rank(Board::init().inc_hand(precomp::board::CHICK)), None);
+}
@@ -0,0 +1,137 @@
+extern crate precomp;
+
+use precomp::{DagNode, Format, Header, Nodes, Order, Records, HEADER_SIZE, RECORD_SIZE};
+use precomp::board::Board;
+
+fn write(format: Format, count: Option<u64>, records: &[(Board, i32)]) -> Vec<u8> {
+    let mut buf = vec![];
+    if format == Format::Binary {
+        Header { stage: 2, order: Order::Depth, idx: false, count }.write(&mut buf).unwrap();
+    }
+    for &(b, depth) in records { format.write(&mut buf, b, depth).unwrap() }
+    buf
+}
+
+fn read(buf: &[u8], check: bool) -> Result<Vec<(Board, i32)>, String> {
+    let records = Records::new(buf, check).map_err(|e| e.to_string())?;
+    records.map(|r| r.map(|(b, depth, _)| (b, depth))).collect()
+}
+
+#[test]
+fn round_trip() {
+    let records = vec![(Board::init(), -1), (Board::init().reverse(), 0), (Board(0xa9001), 77)];
+    for &format in [Format::Text, Format::Binary].iter() {
+        for &count in [None, Some(3)].iter() {
+            let buf = write(format, count, &records);
+            assert_eq!(read(&buf, true), Ok(records.clone()));
+        }
+    }
+
+    let buf = write(Format::Binary, Some(3), &records);
+    assert_eq!(buf.len(), HEADER_SIZE + RECORD_SIZE * 3);
+    let r = Records::new(&buf[..], false).unwrap();
+    assert_eq!(r.format(), Format::Binary);
+    assert_eq!(r.header(), Some(&Header { stage: 2, order: Order::Depth, idx: false, count: Some(3) }));
+
+    let buf = write(Format::Text, None, &records);
+    assert_eq!(String::from_utf8(buf).unwrap().lines().nth(2), Some("0000000000a9001 77"));
+    assert!(read(&[], false).unwrap().is_empty());
+}
+
+#[test]
+fn corrupt() {
+    let records = vec![(Board::init(), -1), (Board(0xa9001), 3)];
+    let buf = write(Format::Binary, Some(2), &records);
+
+    // wrong count
+    assert!(read(&write(Format::Binary, Some(3), &records), false).unwrap_err().starts_with("record 3: truncated"));
+    assert!(read(&write(Format::Binary, Some(1), &records), false).unwrap_err().starts_with("record 2: more records"));
+
+    // partial record
+    assert!(read(&buf[..buf.len() - 1], false).unwrap_err().starts_with("record 2:"));
+    assert!(read(&write(Format::Binary, None, &records)[..buf.len() - 1], false).is_err());
+
+    // broken header
+    assert!(read(&buf[..HEADER_SIZE - 1], false).is_err());
+    let mut b = buf.clone();
+    b[8] = 2; // version
+    assert!(read(&b, false).is_err());
+
+    // invalid board
+    let buf = write(Format::Binary, Some(1), &[(Board(1 << 60), 0)]);
+    assert!(read(&buf, false).is_ok());
+    assert!(read(&buf, true).unwrap_err().starts_with("record 1: invalid board"));
+    assert_eq!(read(b"0000000000a9001 3\nxyz 1\n", false).unwrap_err(), "line 2: invalid board: invalid digit found in string: \"xyz 1\"");
+}
+
+#[test]
+fn move_indexes() {
+    let records = vec![(Board::init(), 5, 0), (Board(0xa9001), 77, 33)];
+    for &format in [Format::Text, Format::Binary].iter() {
+        let mut buf = vec![];
+        if format == Format::Binary {
+            Header { stage: 4, order: Order::Unsorted, idx: true, count: Some(2) }.write(&mut buf).unwrap();
+        }
+        for &(b, depth, idx) in &records { format.write_idx(&mut buf, b, depth, idx).unwrap() }
+        if format == Format::Binary { assert_eq!(buf.len(), HEADER_SIZE + (RECORD_SIZE + 1) * 2) }
+        let r: Result<Vec<_>, _> = Records::new(&buf[..], true).unwrap().collect();
+        assert_eq!(r, Ok(records.clone()));
+    }
+}
+
+fn dag() -> (Vec<u32>, Vec<DagNode>) {
+    let nodes = vec![
+        DagNode { board: Board(0xa9000), depth: 5, name: 0, moves: vec![(0, vec![1]), (3, vec![1, 2])] },
+        DagNode { board: Board(0xa9001), depth: 77, name: 1, moves: vec![(33, vec![])] },
+        DagNode { board: Board(0xa9002), depth: 7, name: 2, moves: vec![] },
+    ];
+    (vec![0, 2], nodes)
+}
+
+fn write_dag(format: Format, count: u64, start: &[u32], nodes: &[DagNode]) -> Vec<u8> {
+    let mut buf = vec![];
+    format.write_start(&mut buf, count, start).unwrap();
+    for node in nodes { format.write_node(&mut buf, node).unwrap() }
+    buf
+}
+
+fn read_dag(buf: &[u8]) -> Result<(Vec<u32>, Vec<DagNode>), String> {
+    let nodes = Nodes::new(buf).map_err(|e| e.to_string())?;
+    let start = nodes.start().to_vec();
+    nodes.collect::<Result<_, _>>().map(|nodes| (start, nodes))
+}
+
+#[test]
+fn dag_round_trip() {
+    let (start, nodes) = dag();
+    for &format in [Format::Text, Format::Binary].iter() {
+        let buf = write_dag(format, 3, &start, &nodes);
+        assert_eq!(Nodes::new(&buf[..]).unwrap().format(), format);
+        assert_eq!(read_dag(&buf), Ok((start.clone(), nodes.clone())));
+    }
+
+    // the text is the same as 3-extract's output
+    let buf = write_dag(Format::Text, 3, &start, &nodes);
+    assert_eq!(String::from_utf8(buf).unwrap(),
+        " 0 2\n0000000000a9000 5 0\n 0: 1\n 3: 1 2\n\n0000000000a9001 77 1\n 33:\n\n0000000000a9002 7 2\n\n");
+
+    // a DAG and records are not mixed up
+    let buf = write_dag(Format::Binary, 3, &start, &nodes);
+    assert!(Records::new(&buf[..], false).is_err());
+    assert!(Nodes::new(&write(Format::Binary, Some(1), &[(Board::init(), 0)])[..]).is_err());
+    assert_eq!(read_dag(b" \n"), Ok((vec![], vec![])));
+}
+
+#[test]
+fn dag_corrupt() {
+    let (start, nodes) = dag();
+    let buf = write_dag(Format::Binary, 3, &start, &nodes);
+    assert!(read_dag(&write_dag(Format::Binary, 4, &start, &nodes)).unwrap_err().starts_with("node 4: truncated"));
+    assert!(read_dag(&write_dag(Format::Binary, 2, &start, &nodes)).unwrap_err().starts_with("node 3: more nodes"));
+    assert!(read_dag(&buf[..buf.len() - 1]).unwrap_err().starts_with("node 3:"));
+
+    assert_eq!(read_dag(b" 0\n0000000000a9001 7 0\n 0: 1\n").unwrap_err(), "line 4: unexpected end of input");
+    assert_eq!(read_dag(b" 0\n0000000000a9001 7 0\n 0 1\n\n").unwrap_err(), "line 3: invalid move: \" 0 1\"");
+    assert_eq!(read_dag(b" 0\n0000000000a9001 7\n\n").unwrap_err(), "line 2: invalid node: \"0000000000a9001 7\"");
+    assert!(read_dag(b" x\n").is_err());
+}
@@ -0,0 +1,39 @@
+extern crate precomp;
+
+use precomp::board::{Board, Color, Result, CHICK};
+use precomp::sfen;
+
+#[test]
+fn initial_board() {
+    let s = "gle/1c1/1C1/ELG b -";
+    assert_eq!(sfen::to_string(Board::init(), Color::Black), s);
+    assert_eq!(sfen::parse(s), Ok((Board::init(), Color::Black)));
+    assert_eq!(s.parse::<Board>(), Ok(Board::init()));
+    assert_eq!("000b0029c41a003".parse::<Board>(), Ok(Board::init()));
+}
+
+#[test]
+fn round_trip() {
+    let b = Board::init().apply(Board::init().legal_moves()[3]);
+    assert_eq!(b.hand(CHICK), 1);
+    let s = sfen::to_string(b.reverse(), Color::White);
+    assert_eq!(s, "gle/1C1/3/ELG w C");
+    assert_eq!(sfen::parse(&s), Ok((b.reverse(), Color::White)));
+
+    if let Result::Unknown(bs) = b.reverse().next() {
+        for nb in bs {
+            for &c in [Color::Black, Color::White].iter() {
+                assert_eq!(sfen::parse(&sfen::to_string(nb, c)), Ok((nb, c)));
+            }
+        }
+    }
+}
+
+#[test]
+fn invalid() {
+    assert!(sfen::parse("gle/1c1/1C1/EL b -").is_err());
+    assert!(sfen::parse("gle/1c1/1C1/ELX b -").is_err());
+    assert!(sfen::parse("gle/1c1/1C1/ELG x -").is_err());
+    assert!(sfen::parse("gle/1c1/1C1/ELG b L").is_err());
+    assert!(sfen::parse("gle/1c1/1C1/ELG b").is_err());
+}
@@ -0,0 +1,81 @@
+extern crate precomp;
+
+mod common;
+
+use precomp::board::{Board, Status};
+use precomp::tablebase::{Tablebase, Value};
+use common::{analyze, enumerate};
+
+fn tablebase(input: &str, format: &str) -> Tablebase {
+    Tablebase::read(&analyze(input, &["--format", format])[..]).unwrap()
+}
+
+#[test]
+fn probe() {
+    let (boards, input) = enumerate();
+    let tb = tablebase(&input, "text");
+    assert_eq!(tb.len(), boards.len());
+    let tb2 = tablebase(&input, "binary");
+    for &b in &boards { assert_eq!(tb2.depth(b), tb.depth(b)) }
+
+    for &b in &boards {
+        let v = tb.probe(b).unwrap();
+        assert_eq!(tb.probe(b.flip()), Some(v));
+        if b.status() == Status::Win { assert_eq!(v, Value::Win(1)) }
+        if b.status() == Status::Lose { assert_eq!(v, Value::Loss(0)) }
+
+        // no move is better than the value of the board (the next boards
+        // of a winning board are not enumerated unless the lion is captured)
+        let ms = b.legal_moves();
+        for &m in &ms {
+            match tb.probe_move(b, m) {
+                Some(v2) => assert!(v2 <= v),
+                None => assert_eq!(b.status(), Status::Win),
+            }
+        }
+
+        // best moves have the value of the board
+        let best = tb.best_moves(b);
+        if v == Value::Loss(0) { assert!(best.is_empty()) } else { assert!(!best.is_empty()) }
+        for &m in &best {
+            assert_eq!(tb.probe_move(b, m), Some(v));
+        }
+        for &m in &ms {
+            if best.contains(&m) { continue }
+            if let Some(v2) = tb.probe_move(b, m) { assert!(v2 < v) }
+        }
+    }
+}
+
+#[test]
+fn depth_one_board_found_again() {
+    // a depth-1 board of the input that is also a previous board of a depth-0
+    // board, with and without other boards (approach 1 and 2 of 2-analyze)
+    let d = "00000090000b100 0\n000009300000100 1\n";
+    for input in [d.to_string(), d.to_string() + &enumerate().1].iter() {
+        for args in [&[][..], &["--counter"][..]].iter() {
+            let out = String::from_utf8(analyze(input, args)).unwrap();
+            assert_eq!(out.lines().count(), input.lines().count());
+            let tb = Tablebase::read(&analyze(input, &[args, &["--format", "binary"][..]].concat())[..]).unwrap();
+            assert_eq!(tb.len(), input.lines().count());
+            assert_eq!(tb.probe(Board(0x000009300000100)), Some(Value::Win(1)));
+        }
+    }
+}
+
+#[test]
+fn value_order() {
+    // a quicker win, a draw, and then a slower loss
+    let vs = [Value::Win(1), Value::Win(3), Value::Draw, Value::Loss(6), Value::Loss(0)];
+    for (i, v) in vs.iter().enumerate() {
+        assert!(vs[i + 1..].iter().all(|v2| v2 < v));
+    }
+}
+
+#[test]
+fn unknown_board() {
+    let tb = Tablebase::read(&b"0000000000a9001 3\n"[..]).unwrap();
+    assert_eq!(tb.probe(Board(0xa9001)), Some(Value::Win(3)));
+    assert_eq!(tb.probe(Board::init()), None);
+    assert!(Tablebase::read(&b"0000000000a9001\n"[..]).is_err());
+}